@@ -1,13 +1,14 @@
 use clap::{AppSettings, Subcommand};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::Command;
 
 pub type SaveCommand = Command<SaveRequest, SaveResponse>;
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Deserialize)]
 #[clap(setting(AppSettings::NoBinaryName))]
 #[clap(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", tag = "action")]
 pub enum SaveRequest {
     Start {},
     End {},
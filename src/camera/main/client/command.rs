@@ -107,6 +107,9 @@ pub(super) async fn cmd_get(
         CameraCommandGetRequest::FocusMode => CameraPropertyCode::FocusMode,
         CameraCommandGetRequest::ZoomLevel => CameraPropertyCode::ZoomAbsolutePosition,
         CameraCommandGetRequest::CcInterval => CameraPropertyCode::IntervalTime,
+        CameraCommandGetRequest::ShutterSpeed => CameraPropertyCode::ShutterSpeed,
+        CameraCommandGetRequest::Iso => CameraPropertyCode::ISO,
+        CameraCommandGetRequest::Aperture => CameraPropertyCode::FNumber,
         CameraCommandGetRequest::Other(_) => todo!(),
     };
 
@@ -152,6 +155,25 @@ pub(super) async fn cmd_get(
             }
             _ => bail!("invalid camera zoom level (wrong data type)"),
         },
+        CameraCommandGetRequest::ShutterSpeed => match prop_info.current {
+            ptp::PtpData::UINT32(speed) => CameraCommandResponse::ShutterSpeed(
+                ShutterSpeed::from_u32(speed)
+                    .context("invalid camera shutter speed (wrong value)")?,
+            ),
+            _ => bail!("invalid camera shutter speed (wrong data type)"),
+        },
+        CameraCommandGetRequest::Iso => match prop_info.current {
+            ptp::PtpData::UINT32(iso) => CameraCommandResponse::Iso(
+                Iso::from_u32(iso).context("invalid camera iso (wrong value)")?,
+            ),
+            _ => bail!("invalid camera iso (wrong data type)"),
+        },
+        CameraCommandGetRequest::Aperture => match prop_info.current {
+            ptp::PtpData::UINT16(aperture) => CameraCommandResponse::Aperture(
+                Aperture::from_u16(aperture).context("invalid camera aperture (wrong value)")?,
+            ),
+            _ => bail!("invalid camera aperture (wrong data type)"),
+        },
         CameraCommandGetRequest::Other(_) => todo!(),
     })
 }
@@ -210,6 +232,10 @@ pub(super) async fn cmd_set(
             CameraPropertyCode::FNumber,
             ptp::PtpData::UINT16(ToPrimitive::to_u16(&aperture).unwrap()),
         ),
+        CameraCommandSetRequest::Iso { iso } => (
+            CameraPropertyCode::ISO,
+            ptp::PtpData::UINT32(ToPrimitive::to_u32(&iso).unwrap()),
+        ),
         // CameraCommandSetRequest::Other(s) => warn!("cannot set {"),
     };
 
@@ -439,6 +465,9 @@ pub(super) async fn cmd_file(
                 image_name: info.filename.clone(),
                 image_data: Arc::new(data),
                 cc_timestamp: None,
+                // this could be an old image, so the current zoom position
+                // says nothing about it
+                lens: None,
             });
 
             Ok(CameraCommandResponse::Download {
@@ -499,11 +499,11 @@ async fn run_commands(
             CameraCommandRequest::File(req) => {
                 cmd_file(interface.clone(), req, client_tx.clone()).await
             }
-            CameraCommandRequest::Reconnect => todo!(),
+            CameraCommandRequest::Reconnect => Err(anyhow!("reconnecting is not supported")),
             CameraCommandRequest::Status => cmd_status(interface.clone()).await,
             CameraCommandRequest::Get(req) => cmd_get(interface.clone(), req).await,
             CameraCommandRequest::Set(req) => cmd_set(interface.clone(), req).await,
-            CameraCommandRequest::Record(_) => todo!(),
+            CameraCommandRequest::Record(_) => Err(anyhow!("recording is not supported")),
         };
 
         let _ = command.chan.send(result);
@@ -521,8 +521,34 @@ async fn run_download(
 
         let event_timestamp = chrono::Local::now();
 
+        // read the zoom now, so that it can't change before the image is
+        // downloaded
+        let zoom_magnification = interface
+            .enter(|i| async move {
+                i.update().await?;
+                Ok::<_, anyhow::Error>(i.get_value(CameraPropertyCode::ZoomMagnificationInfo).await)
+            })
+            .await?;
+
+        let lens = match &zoom_magnification {
+            Some(data) => {
+                let lens = LensState::from_zoom_magnification_info(data);
+
+                if lens.is_none() {
+                    warn!("could not parse zoom magnification of camera: {:?}", data);
+                }
+
+                lens
+            }
+            None => {
+                warn!("could not get zoom magnification of camera");
+                None
+            }
+        };
+
         let _ = client_tx.send(CameraClientEvent::Capture {
-            timestamp: event_timestamp.clone(),
+            timestamp: event_timestamp,
+            lens,
         });
 
         debug!("received camera capture event");
@@ -579,6 +605,7 @@ async fn run_download(
                 image_name: info.filename,
                 image_data: Arc::new(data),
                 cc_timestamp: Some(event_timestamp),
+                lens,
             });
 
             let (new, _) = watch(&interface, CameraPropertyCode::ShootingFileInfo).await?;
@@ -1,7 +1,7 @@
 use std::{collections::HashMap, str::FromStr};
 
 use clap::Subcommand;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::Command;
 
@@ -9,7 +9,8 @@ use super::{interface::OperatingMode, state::*};
 
 pub type CameraCommand = Command<CameraCommandRequest, CameraCommandResponse>;
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
 pub enum CameraCommandRequest {
     /// view information about the storage media inside of the camera
     #[clap(subcommand)]
@@ -23,6 +24,7 @@ pub enum CameraCommandRequest {
     Capture,
 
     /// disconnect and reconnect to the camera
+    #[serde(skip)]
     Reconnect,
 
     Status,
@@ -42,10 +44,12 @@ pub enum CameraCommandRequest {
 
     /// record videos
     #[clap(subcommand)]
+    #[serde(skip)]
     Record(CameraCommandRecordRequest),
 }
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "property")]
 pub enum CameraCommandGetRequest {
     ExposureMode,
     OperatingMode,
@@ -53,12 +57,17 @@ pub enum CameraCommandGetRequest {
     FocusMode,
     ZoomLevel,
     CcInterval,
+    ShutterSpeed,
+    Iso,
+    Aperture,
 
     #[clap(external_subcommand)]
+    #[serde(skip)]
     Other(Vec<String>),
 }
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "property")]
 pub enum CameraCommandSetRequest {
     ExposureMode { mode: ExposureMode },
     OperatingMode { mode: OperatingMode },
@@ -68,17 +77,20 @@ pub enum CameraCommandSetRequest {
     CcInterval { interval: f32 },
     ShutterSpeed { speed: ShutterSpeed },
     Aperture { aperture: Aperture },
+    Iso { iso: Iso },
     // #[clap(external_subcommand)]
     // Other(Vec<String>),
 }
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "action")]
 pub enum CameraCommandStorageRequest {
     /// list the storage volumes available on the camera
     List,
 }
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "action")]
 pub enum CameraCommandFileRequest {
     /// list the files available on the camera
     List {
@@ -141,13 +153,15 @@ impl FromStr for ZoomMode {
     }
 }
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "action")]
 pub enum CameraCommandContinuousCaptureRequest {
     Start,
     Stop,
 }
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "action")]
 pub enum CameraCommandRecordRequest {
     Start,
     Stop,
@@ -201,4 +215,7 @@ pub enum CameraCommandResponse {
     OperatingMode(OperatingMode),
     ExposureMode(ExposureMode),
     FocusMode(FocusMode),
+    ShutterSpeed(ShutterSpeed),
+    Iso(Iso),
+    Aperture(Aperture),
 }
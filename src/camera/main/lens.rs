@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+use crate::geometry::CameraIntrinsics;
+
+/// The R10C has an APS-C sensor that takes 20 MP images.
+const SENSOR_WIDTH: f32 = 23.2;
+const SENSOR_HEIGHT: f32 = 15.4;
+const IMAGE_WIDTH: u32 = 5456;
+const IMAGE_HEIGHT: u32 = 3632;
+
+/// Focal length of the E PZ 16-50mm power zoom lens when it is zoomed all the
+/// way out. The camera reports zoom as a magnification relative to this.
+const WIDE_FOCAL_LENGTH: f32 = 16.;
+
+/// The state of the R10C's lens when an image was taken.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LensState {
+    /// Total zoom magnification reported by the camera, including digital
+    /// zoom
+    pub magnification: f32,
+
+    /// Effective focal length in millimeters, including digital zoom
+    pub focal_length: f32,
+
+    /// Horizontal field of view in degrees
+    pub hfov: f32,
+
+    /// Vertical field of view in degrees
+    pub vfov: f32,
+}
+
+impl LensState {
+    pub fn from_magnification(magnification: f32) -> Self {
+        let focal_length = WIDE_FOCAL_LENGTH * magnification;
+        let intrinsics = intrinsics(focal_length);
+
+        LensState {
+            magnification,
+            focal_length,
+            hfov: intrinsics.hfov(),
+            vfov: intrinsics.vfov(),
+        }
+    }
+
+    /// Reads the lens state from the value of `ZoomMagnificationInfo`. The
+    /// camera reports the magnification either as a string such as `"x2.0"`,
+    /// or as an integer in tenths.
+    pub fn from_zoom_magnification_info(data: &ptp::PtpData) -> Option<Self> {
+        let magnification = match data {
+            ptp::PtpData::STR(s) => s
+                .trim_matches(|c: char| !c.is_ascii_digit() && c != '.')
+                .parse::<f32>()
+                .ok()?,
+            ptp::PtpData::UINT8(n) => *n as f32 / 10.,
+            ptp::PtpData::UINT16(n) => *n as f32 / 10.,
+            ptp::PtpData::UINT32(n) => *n as f32 / 10.,
+            _ => return None,
+        };
+
+        // the lens can't zoom out past its widest setting
+        if !magnification.is_finite() || magnification < 1. {
+            return None;
+        }
+
+        Some(LensState::from_magnification(magnification))
+    }
+
+    pub fn intrinsics(&self) -> CameraIntrinsics {
+        intrinsics(self.focal_length)
+    }
+}
+
+impl Default for LensState {
+    /// The lens zoomed all the way out, which is how the camera starts up.
+    fn default() -> Self {
+        LensState::from_magnification(1.)
+    }
+}
+
+fn intrinsics(focal_length: f32) -> CameraIntrinsics {
+    CameraIntrinsics {
+        focal_length,
+        sensor_width: SENSOR_WIDTH,
+        sensor_height: SENSOR_HEIGHT,
+        image_width: IMAGE_WIDTH,
+        image_height: IMAGE_HEIGHT,
+    }
+}
@@ -3,8 +3,10 @@ pub mod command;
 #[cfg(feature = "csb")]
 pub mod csb;
 mod interface;
+pub mod lens;
 pub mod state;
 
 pub use client::*;
 pub use command::*;
+pub use lens::LensState;
 pub use state::*;
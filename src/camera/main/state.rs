@@ -1,13 +1,15 @@
-use std::{fmt::Display, str::FromStr, sync::Arc};
+use std::{convert::TryFrom, fmt::Display, str::FromStr, sync::Arc};
 
 use anyhow::Context;
 use num_traits::{FromPrimitive, ToPrimitive};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub enum CameraClientEvent {
     Capture {
         timestamp: chrono::DateTime<chrono::Local>,
+        /// The state of the lens when the image was taken, if it is known.
+        lens: Option<super::LensState>,
     },
     Download {
         image_name: String,
@@ -15,12 +17,15 @@ pub enum CameraClientEvent {
         /// The timestamp of this image, if it was received asynchronously via
         /// continuous capture.
         cc_timestamp: Option<chrono::DateTime<chrono::Local>>,
+        /// The state of the lens when this image was taken, if it is known.
+        /// This is the same as the lens state in the capture event.
+        lens: Option<super::LensState>,
     },
     Error(ErrorMode),
 }
 
 #[repr(u16)]
-#[derive(Debug, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Deserialize, Eq, PartialEq)]
 pub enum ExposureMode {
     ManualExposure = 0x0001,
     ProgramAuto,
@@ -36,7 +41,7 @@ pub enum ExposureMode {
 }
 
 #[repr(u16)]
-#[derive(Debug, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Deserialize, Eq, PartialEq)]
 pub enum FocusMode {
     Manual = 0x0001,
     AutoFocusStill = 0x0002,
@@ -69,13 +74,33 @@ pub enum CompressionMode {
 }
 
 #[repr(u16)]
-#[derive(Debug, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Deserialize, Eq, PartialEq)]
 pub enum SaveMedia {
     HostDevice = 0x0001,
     MemoryCard1 = 0x0002,
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Eq, PartialEq)]
+/// An exposure setting as it is written in a request, either as a number or as
+/// a string such as `"auto"` or `"1/250"`. It is parsed with the setting's
+/// `FromStr` implementation.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SettingValue {
+    Number(f64),
+    String(String),
+}
+
+impl Display for SettingValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "SettingValue")]
 pub enum ShutterSpeed {
     /// Bulb
     Bulb,
@@ -144,7 +169,7 @@ impl FromStr for ShutterSpeed {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.to_ascii_uppercase() == "bulb" {
+        if s.eq_ignore_ascii_case("bulb") {
             return Ok(Self::Bulb);
         }
 
@@ -172,7 +197,16 @@ impl FromStr for ShutterSpeed {
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Eq, PartialEq)]
+impl TryFrom<SettingValue> for ShutterSpeed {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SettingValue) -> Result<Self, Self::Error> {
+        value.to_string().parse()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "SettingValue")]
 pub enum Iso {
     Auto,
     Value(u16),
@@ -214,7 +248,30 @@ impl Display for Iso {
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Eq, PartialEq)]
+impl FromStr for Iso {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+
+        let v = u16::from_str(s).context("iso must be 'auto' or a number")?;
+
+        Ok(Self::Value(v))
+    }
+}
+
+impl TryFrom<SettingValue> for Iso {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SettingValue) -> Result<Self, Self::Error> {
+        value.to_string().parse()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "SettingValue")]
 pub enum Aperture {
     Undefined,
     Value(u16),
@@ -271,8 +328,16 @@ impl FromStr for Aperture {
     }
 }
 
+impl TryFrom<SettingValue> for Aperture {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SettingValue) -> Result<Self, Self::Error> {
+        value.to_string().parse()
+    }
+}
+
 #[repr(u16)]
-#[derive(Debug, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Deserialize, Eq, PartialEq)]
 pub enum ErrorMode {
     /// Hardware failure, etc
     Fatal = 0x8000,
@@ -315,3 +380,61 @@ pub enum ErrorMode {
 
     SettingFailure = 0x0001,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn iso_accepts_numbers_and_strings() {
+        assert_eq!(
+            serde_json::from_value::<Iso>(json!(400)).unwrap(),
+            Iso::Value(400)
+        );
+        assert_eq!(
+            serde_json::from_value::<Iso>(json!("400")).unwrap(),
+            Iso::Value(400)
+        );
+        assert_eq!(
+            serde_json::from_value::<Iso>(json!("auto")).unwrap(),
+            Iso::Auto
+        );
+        assert!(serde_json::from_value::<Iso>(json!("fast")).is_err());
+    }
+
+    #[test]
+    fn aperture_accepts_numbers_and_strings() {
+        assert_eq!(
+            serde_json::from_value::<Aperture>(json!(2.8)).unwrap(),
+            Aperture::Value(280)
+        );
+        assert_eq!(
+            serde_json::from_value::<Aperture>(json!("F4")).unwrap(),
+            Aperture::Value(400)
+        );
+    }
+
+    #[test]
+    fn shutter_speed_accepts_numbers_and_strings() {
+        assert_eq!(
+            serde_json::from_value::<ShutterSpeed>(json!("1/250")).unwrap(),
+            ShutterSpeed::Fraction {
+                numerator: 1,
+                denominator: 250
+            }
+        );
+        assert_eq!(
+            serde_json::from_value::<ShutterSpeed>(json!("bulb")).unwrap(),
+            ShutterSpeed::Bulb
+        );
+        assert_eq!(
+            serde_json::from_value::<ShutterSpeed>(json!(2)).unwrap(),
+            ShutterSpeed::Fraction {
+                numerator: 20,
+                denominator: 10
+            }
+        );
+    }
+}
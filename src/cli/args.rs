@@ -7,4 +7,13 @@ pub struct MainArgs {
     /// plane-system.json by default.
     #[clap(parse(from_os_str), long, short)]
     pub config: Option<PathBuf>,
+
+    /// Replay a flight log (or a folder of flight logs) recorded by the flight
+    /// data recorder instead of connecting to the Pixhawk.
+    #[clap(parse(from_os_str), long)]
+    pub replay: Option<PathBuf>,
+
+    /// How fast to replay the flight log, relative to real time.
+    #[clap(long, default_value = "1.0")]
+    pub replay_speed: f64,
 }
@@ -1,15 +1,88 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{collections::BTreeMap, net::SocketAddr, path::PathBuf};
 
 use config::ConfigError;
 use mavlink::MavlinkVersion;
 use serde::Deserialize;
 
-use crate::gimbal::GimbalKind;
+use crate::{
+    gimbal::GimbalKind,
+    gs::GroundServerApi,
+    pixhawk::{transport::PixhawkAddress, ParamKind, PixhawkKind},
+};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PixhawkConfig {
-    pub address: SocketAddr,
+    pub address: PixhawkAddress,
     pub mavlink: MavlinkVersion,
+
+    /// Whether to connect to a real Pixhawk or to simulate one
+    #[serde(default)]
+    pub kind: PixhawkKind,
+
+    /// Parameters that are set on the Pixhawk when the plane system connects
+    /// to it, keyed by parameter name
+    #[serde(default)]
+    pub params: BTreeMap<String, PixhawkParamConfig>,
+
+    /// Message intervals that are set on the Pixhawk when the plane system
+    /// connects to it
+    #[serde(default)]
+    pub message_intervals: Vec<PixhawkMessageIntervalConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PixhawkParamConfig {
+    #[serde(rename = "type", default)]
+    pub kind: ParamKind,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PixhawkMessageIntervalConfig {
+    pub message_id: u32,
+
+    /// The interval between messages in microseconds
+    pub interval_us: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct TelemetryConfig {
+    /// When telemetry is published to the rest of the plane system
+    #[serde(default)]
+    pub publish: TelemetryPublishPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "mode")]
+pub enum TelemetryPublishPolicy {
+    /// Publish every time the telemetry changes
+    OnChange,
+
+    /// Publish at a fixed interval, whether or not the telemetry has changed
+    FixedRate { interval_ms: u64 },
+
+    /// Publish when the telemetry changes, but no more than once per interval
+    RateLimited { min_interval_ms: u64 },
+}
+
+impl Default for TelemetryPublishPolicy {
+    fn default() -> Self {
+        TelemetryPublishPolicy::OnChange
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecorderConfig {
+    /// The folder in which to save flight logs
+    pub path: PathBuf,
+
+    /// The size in megabytes at which a new flight log is started
+    #[serde(default = "default_max_file_size_mb")]
+    pub max_file_size_mb: u64,
+}
+
+fn default_max_file_size_mb() -> u64 {
+    64
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +93,109 @@ pub struct PlaneServerConfig {
 #[derive(Debug, Deserialize)]
 pub struct GroundServerConfig {
     pub address: String,
+
+    /// The version of the ground server's API
+    #[serde(default)]
+    pub api: GroundServerApi,
+
+    /// Paths to send uploads to, which override the defaults for the API
+    /// version
+    #[serde(default)]
+    pub endpoints: GroundServerEndpoints,
+
+    /// The interval between telemetry heartbeats in milliseconds. Heartbeats
+    /// are not sent if this is not set.
+    pub heartbeat_interval_ms: Option<u64>,
+
+    /// The folder in which to keep images that are waiting to be uploaded
+    #[serde(default = "default_queue_path")]
+    pub queue_path: PathBuf,
+
+    /// Low-resolution previews that are sent before the original images. If
+    /// this is not set, the original images are sent as soon as they are
+    /// downloaded.
+    pub preview: Option<GroundServerPreviewConfig>,
+
+    /// A WebSocket connection to the ground server, which is not opened if
+    /// this is not set
+    pub link: Option<GroundLinkConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GroundServerEndpoints {
+    pub image: Option<String>,
+    pub telemetry: Option<String>,
+    pub capture: Option<String>,
+    pub original: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroundServerPreviewConfig {
+    /// The maximum width and height of a preview in pixels
+    #[serde(default = "default_preview_max_size")]
+    pub max_size: u32,
+
+    /// The JPEG quality of a preview, from 1 to 100
+    #[serde(default = "default_preview_quality")]
+    pub quality: u8,
+
+    /// When to send the original images after their previews
+    #[serde(default)]
+    pub send_originals: SendOriginalsPolicy,
+}
+
+fn default_preview_max_size() -> u32 {
+    1280
+}
+
+fn default_preview_quality() -> u8 {
+    60
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SendOriginalsPolicy {
+    /// Send originals whenever there is nothing else to upload, or when the
+    /// ground server asks for them
+    Idle,
+
+    /// Only send originals when the ground server asks for them
+    OnRequest,
+}
+
+impl Default for SendOriginalsPolicy {
+    fn default() -> Self {
+        SendOriginalsPolicy::Idle
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroundLinkConfig {
+    /// The path of the WebSocket endpoint, relative to the ground server's
+    /// address
+    pub path: String,
+
+    /// The interval between telemetry messages in milliseconds
+    #[serde(default = "default_telemetry_interval_ms")]
+    pub telemetry_interval_ms: u64,
+
+    /// The number of messages to keep while the link is down
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+}
+
+fn default_telemetry_interval_ms() -> u64 {
+    1000
+}
+
+fn default_buffer_size() -> usize {
+    1024
+}
+
+fn default_queue_path() -> PathBuf {
+    std::env::current_dir()
+        .expect("could not get current directory")
+        .join("upload-queue")
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,6 +216,10 @@ pub struct ImageConfig {
     /// The folder in which to save downloaded images
     #[serde(default = "default_save_path")]
     pub save_path: PathBuf,
+
+    /// Whether to embed GPS and attitude metadata in JPEG images
+    #[serde(default)]
+    pub embed_metadata: bool,
 }
 
 fn default_save_path() -> PathBuf {
@@ -86,6 +266,8 @@ pub struct AuxCameraSaveConfig {
 #[derive(Debug, Deserialize)]
 pub struct PlaneSystemConfig {
     pub pixhawk: Option<PixhawkConfig>,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
     pub plane_server: PlaneServerConfig,
     pub ground_server: Option<GroundServerConfig>,
     pub image: Option<ImageConfig>,
@@ -93,6 +275,7 @@ pub struct PlaneSystemConfig {
     pub aux_camera: Option<AuxCameraConfig>,
     pub gimbal: Option<GimbalConfig>,
     pub scheduler: Option<SchedulerConfig>,
+    pub recorder: Option<RecorderConfig>,
 }
 
 impl PlaneSystemConfig {
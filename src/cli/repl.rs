@@ -12,6 +12,7 @@ use crate::{
     camera::main::{CameraCommandRequest, CameraCommandResponse, SaveMedia},
     gimbal::GimbalRequest,
     gs::GroundServerRequest,
+    pixhawk::{PixhawkRequest, PixhawkResponse},
     Channels, Command,
 };
 
@@ -35,6 +36,9 @@ enum Commands {
     #[clap(subcommand)]
     Gimbal(GimbalRequest),
 
+    #[clap(subcommand)]
+    Pixhawk(PixhawkRequest),
+
     #[clap(subcommand)]
     #[clap(name = "gs")]
     GroundServer(GroundServerRequest),
@@ -128,7 +132,29 @@ pub async fn run(channels: Arc<Channels>) -> anyhow::Result<()> {
                     }
                     let _ = chan.await?;
                 }
-                Commands::GroundServer(request) => match request {},
+                Commands::Pixhawk(request) => {
+                    let (cmd, chan) = Command::new(request);
+                    if let Err(err) = channels.pixhawk_cmd.clone().send(cmd) {
+                        error!("pixhawk client not available: {}", err);
+                        continue;
+                    }
+
+                    match chan.await? {
+                        Ok(response) => format_pixhawk_response(response),
+                        Err(err) => println!("{}", format!("error: {:?}", err).red()),
+                    };
+                }
+                Commands::GroundServer(request) => {
+                    let (cmd, chan) = Command::new(request);
+                    if let Err(err) = channels.ground_server_cmd.clone().send(cmd) {
+                        error!("ground server client not available: {}", err);
+                        continue;
+                    }
+
+                    if let Err(err) = chan.await? {
+                        println!("{}", format!("error: {:?}", err).red());
+                    }
+                }
                 Commands::Exit => {
                     info!("exiting");
                     let _ = channels.interrupt.send(());
@@ -187,6 +213,67 @@ fn table_format() -> prettytable::format::TableFormat {
         .build()
 }
 
+fn format_pixhawk_response(response: PixhawkResponse) -> () {
+    match response {
+        PixhawkResponse::Unit => println!("done"),
+        PixhawkResponse::Param { id, value } => println!("{} = {}", id, value),
+        PixhawkResponse::CommandResult { result } => println!("result: {}", result),
+        PixhawkResponse::ParamDump { path, count } => {
+            println!("saved {} parameters to {}", count, path.display())
+        }
+        PixhawkResponse::Mission { items } => {
+            let mut table = Table::new();
+            table.add_row(row![
+                "seq",
+                "command",
+                "frame",
+                "params",
+                "latitude",
+                "longitude",
+                "altitude"
+            ]);
+
+            for (seq, item) in items.into_iter().enumerate() {
+                table.add_row(row![
+                    seq,
+                    item.command,
+                    item.frame,
+                    format!("{:?}", item.params),
+                    item.latitude,
+                    item.longitude,
+                    item.altitude
+                ]);
+            }
+
+            table.set_format(table_format());
+            table.printstd();
+        }
+        PixhawkResponse::LinkStats(stats) => {
+            let mut table = Table::new();
+            table.add_row(row!["component", "received", "lost"]);
+
+            let mut components = stats.components.into_iter().collect::<Vec<_>>();
+            components.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (component, component_stats) in components {
+                table.add_row(row![
+                    component,
+                    component_stats.received,
+                    component_stats.lost
+                ]);
+            }
+
+            table.set_format(table_format());
+            table.printstd();
+
+            println!("bad checksum: {}", stats.bad_checksum);
+            println!("bad payload: {}", stats.bad_payload);
+            println!("unsupported: {}", stats.unsupported);
+            println!("skipped bytes: {}", stats.skipped_bytes);
+        }
+    }
+}
+
 fn format_camera_response(response: CameraCommandResponse) -> () {
     match response {
         CameraCommandResponse::Unit => println!("done"),
@@ -395,5 +482,14 @@ fn format_camera_response(response: CameraCommandResponse) -> () {
         CameraCommandResponse::CcInterval(interval) => {
             println!("continuous capture interval: {:?}", interval);
         }
+        CameraCommandResponse::ShutterSpeed(shutter_speed) => {
+            println!("shutter speed: {}", shutter_speed);
+        }
+        CameraCommandResponse::Iso(iso) => {
+            println!("iso: {}", iso);
+        }
+        CameraCommandResponse::Aperture(aperture) => {
+            println!("aperture width: {}", aperture);
+        }
     }
 }
@@ -0,0 +1,256 @@
+use geo::algorithm::haversine_destination::HaversineDestination;
+use serde::Serialize;
+
+use crate::state::{Attitude, Telemetry};
+
+/// The optical properties of a camera, which determine which part of the
+/// ground ends up in each pixel.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CameraIntrinsics {
+    /// Focal length of the lens in millimeters
+    pub focal_length: f32,
+
+    /// Width of the sensor in millimeters
+    pub sensor_width: f32,
+
+    /// Height of the sensor in millimeters
+    pub sensor_height: f32,
+
+    /// Width of the image in pixels
+    pub image_width: u32,
+
+    /// Height of the image in pixels
+    pub image_height: u32,
+}
+
+impl CameraIntrinsics {
+    /// Horizontal field of view in degrees.
+    pub fn hfov(&self) -> f32 {
+        2. * (self.sensor_width / (2. * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn vfov(&self) -> f32 {
+        2. * (self.sensor_height / (2. * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    /// Focal length in pixels.
+    fn focal_length_px(&self) -> f32 {
+        self.focal_length * self.image_width as f32 / self.sensor_width
+    }
+}
+
+/// The points on the ground at the corners of an image.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Footprint {
+    #[serde(serialize_with = "crate::util::serialize_point")]
+    pub top_left: geo::Point<f32>,
+    #[serde(serialize_with = "crate::util::serialize_point")]
+    pub top_right: geo::Point<f32>,
+    #[serde(serialize_with = "crate::util::serialize_point")]
+    pub bottom_right: geo::Point<f32>,
+    #[serde(serialize_with = "crate::util::serialize_point")]
+    pub bottom_left: geo::Point<f32>,
+}
+
+impl Footprint {
+    /// The footprint as a polygon in longitude and latitude.
+    pub fn polygon(&self) -> geo::Polygon<f32> {
+        geo::Polygon::new(
+            geo::LineString::from(vec![
+                self.top_left,
+                self.top_right,
+                self.bottom_right,
+                self.bottom_left,
+                self.top_left,
+            ]),
+            vec![],
+        )
+    }
+}
+
+type Matrix = [[f32; 3]; 3];
+type Vector = [f32; 3];
+
+/// Returns the rotation matrix for roll, pitch and yaw in degrees, applied in
+/// that order.
+fn rotation(attitude: &Attitude) -> Matrix {
+    let (sr, cr) = attitude.roll.to_radians().sin_cos();
+    let (sp, cp) = attitude.pitch.to_radians().sin_cos();
+    let (sy, cy) = attitude.yaw.to_radians().sin_cos();
+
+    [
+        [cy * cp, cy * sp * sr - sy * cr, cy * sp * cr + sy * sr],
+        [sy * cp, sy * sp * sr + cy * cr, sy * sp * cr - cy * sr],
+        [-sp, cp * sr, cp * cr],
+    ]
+}
+
+fn apply(m: &Matrix, v: Vector) -> Vector {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+/// Finds the point on the ground that is visible at pixel (`x`, `y`) of an
+/// image, where (0, 0) is the top left corner of the image.
+///
+/// The camera is assumed to point straight down when the gimbal is level,
+/// with the top of the image towards the nose of the plane. The gimbal's roll,
+/// pitch and yaw are relative to the plane, and follow the same conventions as
+/// the plane's. The ground is assumed to be flat. Returns `None` if the pixel
+/// is above the horizon.
+pub fn pixel_to_location(
+    telemetry: &Telemetry,
+    intrinsics: &CameraIntrinsics,
+    x: f32,
+    y: f32,
+) -> Option<geo::Point<f32>> {
+    let f = intrinsics.focal_length_px();
+
+    // ray through the pixel in the camera frame: right, down, forward
+    let cx = (x - intrinsics.image_width as f32 / 2.) / f;
+    let cy = (y - intrinsics.image_height as f32 / 2.) / f;
+
+    // in the body frame (forward, right, down), the camera looks down and the
+    // top of the image is towards the nose
+    let ray = [-cy, cx, 1.];
+
+    // rotate into the north, east, down frame
+    let ray = apply(&rotation(&telemetry.gimbal_attitude), ray);
+    let [north, east, down] = apply(&rotation(&telemetry.plane_attitude), ray);
+
+    if down <= 0. {
+        return None;
+    }
+
+    let scale = telemetry.position.altitude_rel.max(0.) / down;
+    let (north, east) = (north * scale, east * scale);
+
+    let bearing = east.atan2(north).to_degrees();
+    let distance = north.hypot(east);
+
+    Some(
+        telemetry
+            .position
+            .point
+            .haversine_destination(bearing, distance),
+    )
+}
+
+/// Finds the corners of the area on the ground that is visible in an image.
+/// Returns `None` if any of the corners are above the horizon.
+pub fn footprint(telemetry: &Telemetry, intrinsics: &CameraIntrinsics) -> Option<Footprint> {
+    let w = intrinsics.image_width as f32;
+    let h = intrinsics.image_height as f32;
+
+    Some(Footprint {
+        top_left: pixel_to_location(telemetry, intrinsics, 0., 0.)?,
+        top_right: pixel_to_location(telemetry, intrinsics, w, 0.)?,
+        bottom_right: pixel_to_location(telemetry, intrinsics, w, h)?,
+        bottom_left: pixel_to_location(telemetry, intrinsics, 0., h)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::algorithm::haversine_distance::HaversineDistance;
+
+    use super::*;
+
+    const ALTITUDE: f32 = 100.;
+
+    /// A 10 mm lens on a 10 mm wide sensor, which sees a patch of ground as
+    /// wide as the camera is high.
+    const INTRINSICS: CameraIntrinsics = CameraIntrinsics {
+        focal_length: 10.,
+        sensor_width: 10.,
+        sensor_height: 7.5,
+        image_width: 4000,
+        image_height: 3000,
+    };
+
+    fn telemetry(gimbal_attitude: Attitude) -> Telemetry {
+        let mut telemetry = Telemetry::default();
+        telemetry.position.point = geo::Point::new(-76.48, 42.44);
+        telemetry.position.altitude_rel = ALTITUDE;
+        telemetry.gimbal_attitude = gimbal_attitude;
+        telemetry
+    }
+
+    fn center(telemetry: &Telemetry) -> geo::Point<f32> {
+        pixel_to_location(telemetry, &INTRINSICS, 2000., 1500.).unwrap()
+    }
+
+    /// Coordinates are stored as f32, which only resolves about half a meter
+    /// at these latitudes and longitudes, so distances are compared loosely.
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 2.,
+            "expected {} m, got {} m",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn nadir_footprint_has_expected_size() {
+        let telemetry = telemetry(Attitude::default());
+        let footprint = footprint(&telemetry, &INTRINSICS).unwrap();
+
+        assert_close(
+            footprint.top_left.haversine_distance(&footprint.top_right),
+            ALTITUDE,
+        );
+        assert_close(
+            footprint
+                .top_left
+                .haversine_distance(&footprint.bottom_left),
+            ALTITUDE * 0.75,
+        );
+        assert_close(
+            center(&telemetry).haversine_distance(&telemetry.position.point),
+            0.,
+        );
+
+        // the top of the image is towards the nose, which points north
+        assert!(footprint.top_left.y() > footprint.bottom_left.y());
+        assert!(footprint.top_right.x() > footprint.top_left.x());
+    }
+
+    #[test]
+    fn pitched_gimbal_shifts_footprint_forward() {
+        let telemetry = telemetry(Attitude::new(0., 30., 0.));
+        let center = center(&telemetry);
+
+        assert_close(
+            center.haversine_distance(&telemetry.position.point),
+            ALTITUDE * 30f32.to_radians().tan(),
+        );
+        assert!(center.y() > telemetry.position.point.y());
+    }
+
+    #[test]
+    fn gimbal_yaw_rotates_footprint() {
+        let telemetry = telemetry(Attitude::new(0., 30., 90.));
+        let center = center(&telemetry);
+
+        assert_close(
+            center.haversine_distance(&telemetry.position.point),
+            ALTITUDE * 30f32.to_radians().tan(),
+        );
+
+        // yawed to the right, so the camera looks east instead of north
+        assert!(center.x() > telemetry.position.point.x());
+        assert_close(
+            center.haversine_distance(&geo::Point::new(center.x(), telemetry.position.point.y())),
+            0.,
+        );
+    }
+}
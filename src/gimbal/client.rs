@@ -1,15 +1,21 @@
 use anyhow::Context;
 
 use futures::FutureExt;
-use std::{path::Path, sync::Arc};
+use std::{path::Path, sync::Arc, time::Duration};
 
 use crate::Channels;
 
 use super::{
     interface::{GimbalInterface, HardwareGimbalInterface, SoftwareGimbalInterface},
-    GimbalCommand, GimbalKind, GimbalRequest, GimbalResponse,
+    GimbalCommand, GimbalEvent, GimbalKind, GimbalRequest, GimbalResponse,
 };
 
+/// How often to ask the gimbal for its angles.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for the gimbal to report its angles.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub struct GimbalClient {
     iface: Box<dyn GimbalInterface + Send>,
     channels: Arc<Channels>,
@@ -67,6 +73,9 @@ impl GimbalClient {
         let interrupt_fut = interrupt_recv.recv().fuse();
         futures::pin_mut!(interrupt_fut);
 
+        let mut poll_interval = tokio::time::interval(POLL_INTERVAL);
+        poll_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
         loop {
             futures::select! {
                 cmd = self.cmd.recv_async().fuse() => {
@@ -75,6 +84,11 @@ impl GimbalClient {
                         let _ = cmd.respond(result);
                     }
                 }
+                _ = poll_interval.tick().fuse() => {
+                    if let Err(err) = self.poll().await {
+                        warn!("failed to get gimbal angles: {:#}", err);
+                    }
+                }
                 _ = interrupt_fut => break,
             }
         }
@@ -82,6 +96,22 @@ impl GimbalClient {
         Ok(())
     }
 
+    /// Asks the gimbal for its angles and publishes them.
+    async fn poll(&mut self) -> anyhow::Result<()> {
+        let attitude = tokio::time::timeout(POLL_TIMEOUT, self.iface.get_angles())
+            .await
+            .context("gimbal did not report its angles in time")??;
+
+        trace!("gimbal attitude: {:?}", attitude);
+
+        let _ = self
+            .channels
+            .gimbal_event
+            .send(GimbalEvent::Attitude { attitude });
+
+        Ok(())
+    }
+
     async fn exec(&mut self, cmd: &GimbalRequest) -> anyhow::Result<GimbalResponse> {
         match cmd {
             GimbalRequest::Control { roll, pitch } => {
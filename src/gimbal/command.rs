@@ -1,12 +1,13 @@
 use clap::Subcommand;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::Command;
 
 pub type GimbalCommand = Command<GimbalRequest, GimbalResponse>;
 
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
 #[clap(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", tag = "type")]
 pub enum GimbalRequest {
     Control { roll: f64, pitch: f64 },
 }
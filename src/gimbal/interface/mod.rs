@@ -1,6 +1,8 @@
 use num_traits::FromPrimitive;
 use serde::Deserialize;
 
+use crate::state::Attitude;
+
 // real gimbal
 pub mod hardware;
 
@@ -24,9 +26,16 @@ pub enum GimbalKind {
     Software,
 }
 
+/// SimpleBGC reports angles in units of 360 / 2^14 degrees.
+const SBGC_ANGLE_UNIT: f32 = 360.0 / 16384.0;
+
 #[async_trait]
 pub trait GimbalInterface: Send {
     async fn control_angles(&mut self, roll: f64, pitch: f64) -> anyhow::Result<()>;
+
+    /// Asks the gimbal for the angles that it is currently pointing at, as
+    /// measured by its IMU.
+    async fn get_angles(&mut self) -> anyhow::Result<Attitude>;
 }
 
 #[async_trait]
@@ -62,4 +71,23 @@ impl<T: SimpleBgcGimbalInterface> GimbalInterface for T {
 
         Ok(())
     }
+
+    async fn get_angles(&mut self) -> anyhow::Result<Attitude> {
+        self.send_command(OutgoingCommand::GetAngles).await?;
+
+        // the gimbal may send other messages before it replies
+        loop {
+            match self.recv_command().await? {
+                Some(IncomingCommand::GetAngles(angles)) => {
+                    return Ok(Attitude::new(
+                        angles.roll.imu_angle as f32 * SBGC_ANGLE_UNIT,
+                        angles.pitch.imu_angle as f32 * SBGC_ANGLE_UNIT,
+                        angles.yaw.imu_angle as f32 * SBGC_ANGLE_UNIT,
+                    ));
+                }
+                Some(_) => continue,
+                None => bail!("gimbal connection closed"),
+            }
+        }
+    }
 }
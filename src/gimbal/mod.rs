@@ -1,10 +1,12 @@
 pub mod client;
 pub mod command;
 mod interface;
+pub mod state;
 
 pub use client::*;
 pub use command::*;
 pub use interface::GimbalKind;
+pub use state::*;
 
 pub struct GimbalPosition {
     /// The roll of the gimbal in degrees.
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+use crate::state::Attitude;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GimbalEvent {
+    /// Sent whenever the gimbal reports its angles.
+    Attitude { attitude: Attitude },
+}
@@ -0,0 +1,353 @@
+use std::{collections::VecDeque, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpStream, sync::oneshot, time::Instant};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::{
+    camera::main::{CameraClientEvent, CameraCommandRequest, LensState},
+    cli::config::GroundLinkConfig,
+    gimbal::GimbalRequest,
+    scheduler::{Roi, SchedulerCommand},
+    state::Telemetry,
+    Channels, Command,
+};
+
+use super::{GroundServerRequest, GroundServerStatus};
+
+/// How long to wait before reconnecting after the link drops. The delay
+/// doubles after each failed attempt, up to `MAX_RECONNECT_DELAY`, and is only
+/// reset once a connection has stayed up for `MAX_RECONNECT_DELAY`.
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// How long a connection attempt can take before it is abandoned. Over the
+/// radio link, a connection attempt can otherwise hang for minutes.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type Connecting = Pin<Box<dyn Future<Output = anyhow::Result<Socket>> + Send>>;
+
+/// A message sent from the plane to the ground server.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum UplinkMessage {
+    Telemetry {
+        telemetry: Telemetry,
+    },
+    Capture {
+        #[serde(serialize_with = "crate::util::serialize_time")]
+        timestamp: chrono::DateTime<chrono::Local>,
+        telemetry: Option<Telemetry>,
+        lens: Option<LensState>,
+    },
+    UploadStatus {
+        status: GroundServerStatus,
+    },
+    CommandResult {
+        id: Option<u64>,
+        #[serde(flatten)]
+        result: CommandResult,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CommandResult {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+/// A message sent from the ground server to the plane. Commands can have an
+/// `id`, which is included in the result so that the ground server can tell
+/// which command it belongs to.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DownlinkMessage {
+    Rois {
+        id: Option<u64>,
+        rois: Vec<Roi>,
+    },
+    Camera {
+        id: Option<u64>,
+        request: CameraCommandRequest,
+    },
+    Gimbal {
+        id: Option<u64>,
+        request: GimbalRequest,
+    },
+    GroundServer {
+        id: Option<u64>,
+        request: GroundServerRequest,
+    },
+}
+
+/// Messages that are waiting to be sent. When the buffer is full, the oldest
+/// messages are dropped. Telemetry and upload status messages are only useful
+/// while they are current, so only the latest of each is kept.
+struct Outbox {
+    messages: VecDeque<UplinkMessage>,
+    capacity: usize,
+}
+
+impl Outbox {
+    fn push(&mut self, message: UplinkMessage) {
+        match message {
+            UplinkMessage::Telemetry { .. } => self
+                .messages
+                .retain(|m| !matches!(m, UplinkMessage::Telemetry { .. })),
+            UplinkMessage::UploadStatus { .. } => self
+                .messages
+                .retain(|m| !matches!(m, UplinkMessage::UploadStatus { .. })),
+            _ => {}
+        }
+
+        if self.messages.len() >= self.capacity {
+            self.messages.pop_front();
+        }
+
+        self.messages.push_back(message);
+    }
+}
+
+/// Maintains a WebSocket connection to the ground server, which carries
+/// telemetry, captures and upload progress up to the ground server, and ROI,
+/// camera, gimbal and upload commands down to the plane.
+pub async fn run(
+    channels: Arc<Channels>,
+    address: String,
+    config: GroundLinkConfig,
+) -> anyhow::Result<()> {
+    let mut url = reqwest::Url::parse(&address)
+        .and_then(|url| url.join(&config.path))
+        .context("invalid ground link url")?;
+
+    let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+    url.set_scheme(scheme)
+        .map_err(|_| anyhow!("invalid ground link url"))?;
+
+    let mut interrupt_recv = channels.interrupt.subscribe();
+    let mut camera_recv = channels.camera_event.subscribe();
+    let mut status_recv = channels.ground_server_status.clone();
+
+    let (result_tx, result_rx) = flume::unbounded();
+
+    let mut outbox = Outbox {
+        messages: VecDeque::new(),
+        capacity: config.buffer_size,
+    };
+
+    let mut telemetry_interval =
+        tokio::time::interval(Duration::from_millis(config.telemetry_interval_ms));
+
+    let mut socket: Option<Socket> = None;
+    let mut connecting: Option<Connecting> = None;
+    let mut connected_at = None;
+    let mut reconnect_delay = INITIAL_RECONNECT_DELAY;
+    let mut next_attempt = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(next_attempt), if socket.is_none() && connecting.is_none() => {
+                // the connection is made in the background, so that the rest
+                // of the link keeps running while it is in progress
+                let url = url.to_string();
+
+                connecting = Some(Box::pin(async move {
+                    let (socket, _) = tokio::time::timeout(CONNECT_TIMEOUT, tokio_tungstenite::connect_async(url))
+                        .await
+                        .context("timed out")??;
+
+                    Ok::<_, anyhow::Error>(socket)
+                }));
+            }
+            result = connect(&mut connecting) => {
+                connecting = None;
+
+                match result {
+                    Ok(s) => {
+                        info!("connected to ground server at {}", url);
+                        socket = Some(s);
+                        connected_at = Some(Instant::now());
+                    }
+                    Err(err) => {
+                        debug!("could not connect to ground server, retrying in {:?}: {}", reconnect_delay, err);
+                        next_attempt = Instant::now() + reconnect_delay;
+                        reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                    }
+                }
+            }
+            message = recv(&mut socket) => {
+                match message {
+                    Some(Ok(Message::Text(text))) => {
+                        match serde_json::from_str(&text) {
+                            Ok(message) => dispatch(&channels, message, &result_tx),
+                            Err(err) => warn!("received invalid message from ground server: {}", err),
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => {
+                        warn!("lost connection to ground server: {}", err);
+                        socket = None;
+                    }
+                    None => {
+                        warn!("ground server closed the connection");
+                        socket = None;
+                    }
+                }
+            }
+            _ = telemetry_interval.tick() => {
+                if let Some(telemetry) = *channels.pixhawk_telemetry.borrow() {
+                    outbox.push(UplinkMessage::Telemetry { telemetry });
+                }
+            }
+            camera_evt = camera_recv.recv() => {
+                if let Ok(CameraClientEvent::Capture { timestamp, lens }) = camera_evt {
+                    let telemetry = channels
+                        .telemetry_history
+                        .lock()
+                        .unwrap()
+                        .telemetry_at(timestamp)
+                        .or_else(|| *channels.pixhawk_telemetry.borrow());
+
+                    outbox.push(UplinkMessage::Capture { timestamp, telemetry, lens });
+                }
+            }
+            Ok(()) = status_recv.changed() => {
+                if let Some(status) = status_recv.borrow().clone() {
+                    outbox.push(UplinkMessage::UploadStatus { status });
+                }
+            }
+            Ok(message) = result_rx.recv_async() => {
+                outbox.push(message);
+            }
+            _ = interrupt_recv.recv() => {
+                break;
+            }
+        }
+
+        if let Some(s) = &mut socket {
+            if let Err(err) = flush(s, &mut outbox).await {
+                warn!("lost connection to ground server: {}", err);
+                socket = None;
+            }
+        }
+
+        if socket.is_none() {
+            if let Some(connected_at) = connected_at.take() {
+                // a server that accepts the connection and then drops it
+                // right away is treated the same as one that can't be reached
+                if connected_at.elapsed() >= MAX_RECONNECT_DELAY {
+                    reconnect_delay = INITIAL_RECONNECT_DELAY;
+                }
+
+                next_attempt = Instant::now() + reconnect_delay;
+                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+
+    if let Some(mut s) = socket {
+        let _ = s.close(None).await;
+    }
+
+    Ok(())
+}
+
+async fn recv(
+    socket: &mut Option<Socket>,
+) -> Option<Result<Message, tokio_tungstenite::tungstenite::Error>> {
+    match socket {
+        Some(socket) => socket.next().await,
+        None => futures::future::pending().await,
+    }
+}
+
+async fn connect(connecting: &mut Option<Connecting>) -> anyhow::Result<Socket> {
+    match connecting {
+        Some(connecting) => connecting.await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// Sends the messages in the outbox, and removes them once they have been
+/// sent.
+async fn flush(socket: &mut Socket, outbox: &mut Outbox) -> anyhow::Result<()> {
+    while let Some(message) = outbox.messages.front() {
+        let text = serde_json::to_string(message).context("failed to serialize message")?;
+        socket.send(Message::Text(text)).await?;
+        outbox.messages.pop_front();
+    }
+
+    Ok(())
+}
+
+/// Passes a command from the ground server to the part of the plane system
+/// that handles it. The result is sent back to the ground server when it is
+/// ready.
+fn dispatch(
+    channels: &Arc<Channels>,
+    message: DownlinkMessage,
+    results: &flume::Sender<UplinkMessage>,
+) {
+    debug!("received command from ground server: {:?}", message);
+
+    let channels = channels.clone();
+    let results = results.clone();
+
+    tokio::spawn(async move {
+        let (id, result) = match message {
+            DownlinkMessage::Rois { id, rois } => {
+                let (tx, rx) = oneshot::channel();
+
+                let result = match channels
+                    .scheduler_cmd
+                    .send(SchedulerCommand::AddROIs { rois, tx })
+                {
+                    Ok(()) => match rx.await {
+                        Ok(()) => CommandResult::Ok(serde_json::Value::Null),
+                        Err(_) => CommandResult::Error("scheduler dropped the command".to_owned()),
+                    },
+                    Err(_) => CommandResult::Error("scheduler not available".to_owned()),
+                };
+
+                (id, result)
+            }
+            DownlinkMessage::Camera { id, request } => {
+                (id, call(&channels.camera_cmd, request, "camera").await)
+            }
+            DownlinkMessage::Gimbal { id, request } => {
+                (id, call(&channels.gimbal_cmd, request, "gimbal").await)
+            }
+            DownlinkMessage::GroundServer { id, request } => (
+                id,
+                call(&channels.ground_server_cmd, request, "ground server").await,
+            ),
+        };
+
+        let _ = results.send(UplinkMessage::CommandResult { id, result });
+    });
+}
+
+async fn call<Req, Res: Serialize>(
+    sender: &flume::Sender<Command<Req, Res>>,
+    request: Req,
+    name: &str,
+) -> CommandResult {
+    let (cmd, chan) = Command::new(request);
+
+    if sender.send(cmd).is_err() {
+        return CommandResult::Error(format!("{} client not available", name));
+    }
+
+    match chan.await {
+        Ok(Ok(response)) => match serde_json::to_value(response) {
+            Ok(response) => CommandResult::Ok(response),
+            Err(err) => CommandResult::Error(format!("failed to serialize response: {}", err)),
+        },
+        Ok(Err(err)) => CommandResult::Error(format!("{:?}", err)),
+        Err(_) => CommandResult::Error(format!("{} client dropped the command", name)),
+    }
+}
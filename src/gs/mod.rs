@@ -1,169 +1,680 @@
-use std::{ffi::OsStr, str::FromStr, sync::Arc};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::Context;
 use clap::{AppSettings, Subcommand};
-use futures::{select, FutureExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{broadcast::error::RecvError, watch},
+    time::Instant,
+};
 
 use reqwest;
 
-use crate::state::*;
-use serde_json::json;
+use crate::{
+    camera::main::CameraClientEvent,
+    cli::config::{
+        GroundServerConfig, GroundServerEndpoints, GroundServerPreviewConfig, SendOriginalsPolicy,
+    },
+    image::ImageClientEvent,
+    Channels, Command,
+};
 
-use crate::{image::ImageClientEvent, Channels};
+pub mod link;
+mod preview;
+mod queue;
+pub mod schema;
 
-#[derive(Subcommand, Debug, Clone)]
+pub use self::schema::GroundServerApi;
+use self::{
+    queue::{QueuedUpload, UploadQueue},
+    schema::{UploadBody, UploadKind, UploadSchema},
+};
+
+/// How long to wait before retrying after the first failed upload. The delay
+/// doubles after each consecutive failure, up to `MAX_RETRY_DELAY`.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// How long an upload can take before it is abandoned and retried. Without
+/// this, a request sent just before the radio link drops can hang forever.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub type GroundServerCommand = Command<GroundServerRequest, GroundServerResponse>;
+
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
 #[clap(setting(AppSettings::NoBinaryName))]
 #[clap(rename_all = "kebab-case")]
-pub enum GroundServerRequest {}
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum GroundServerRequest {
+    /// Sends the full-resolution version of an image whose preview has
+    /// already been sent, ahead of the other originals.
+    UploadOriginal { id: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum GroundServerResponse {
+    Unit,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GroundServerStatus {
+    /// Number of uploads waiting to be sent
+    pub queue_length: usize,
+
+    /// Number of original images waiting to be sent after their previews
+    pub originals_queue_length: usize,
+
+    /// Number of uploads sent since the plane system started
+    pub uploaded: u64,
+
+    /// Number of failed upload attempts since the plane system started
+    pub failures: u64,
+
+    /// Number of downloaded images that were never queued, because the ground
+    /// server client fell too far behind the image client
+    pub missed_images: u64,
+
+    /// Number of times in a row that the upload at the front of the queue has
+    /// failed
+    pub consecutive_failures: u32,
+
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    pub last_upload: Option<chrono::DateTime<chrono::Local>>,
+
+    pub last_error: Option<String>,
+}
+
+enum UploadResult {
+    Sent,
+
+    /// The upload failed, but might succeed if it is tried again. If the
+    /// ground server said how long to wait before trying again, that delay is
+    /// included.
+    Failed(anyhow::Error, Option<Duration>),
+
+    /// The upload can never succeed, so it should be dropped.
+    Rejected(anyhow::Error),
+}
+
+/// The queue that an upload was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UploadSource {
+    Queue,
+    Originals,
+}
+
+/// An upload that is being sent. It stays at the front of its queue until the
+/// result comes back.
+struct InFlightUpload {
+    source: UploadSource,
+    id: Option<String>,
+    temporary_file: Option<PathBuf>,
+}
 
 pub struct GroundServerClient {
     channels: Arc<Channels>,
+    cmd: flume::Receiver<GroundServerCommand>,
     http: reqwest::Client,
     base_url: reqwest::Url,
+    schema: Box<dyn UploadSchema>,
+    config: GroundServerConfig,
+    status: watch::Sender<Option<GroundServerStatus>>,
 }
 
 impl GroundServerClient {
-    pub fn new(channels: Arc<Channels>, base_url: String) -> anyhow::Result<Self> {
-        Ok(GroundServerClient {
+    pub fn new(
+        channels: Arc<Channels>,
+        cmd: flume::Receiver<GroundServerCommand>,
+        config: GroundServerConfig,
+        status: watch::Sender<Option<GroundServerStatus>>,
+    ) -> anyhow::Result<Self> {
+        let client = GroundServerClient {
             channels,
-            base_url: reqwest::Url::from_str(&base_url).context("invalid ground server url")?,
-            http: reqwest::Client::new(),
-        })
+            cmd,
+            base_url: reqwest::Url::from_str(&config.address)
+                .context("invalid ground server url")?,
+            http: reqwest::Client::builder()
+                .timeout(UPLOAD_TIMEOUT)
+                .build()
+                .context("could not create http client")?,
+            schema: config.api.schema(),
+            config,
+            status,
+        };
+
+        // check the endpoints now, so that a typo in the config is reported
+        // at startup instead of when the first upload is sent
+        for kind in [
+            UploadKind::Image,
+            UploadKind::Telemetry,
+            UploadKind::Capture,
+            UploadKind::Original,
+        ] {
+            if let Some(endpoint) = client.endpoint(kind) {
+                client.url(&endpoint)?;
+            }
+        }
+
+        Ok(client)
     }
 
     pub async fn run(self) -> anyhow::Result<()> {
         let mut interrupt_recv = self.channels.interrupt.subscribe();
         let mut image_recv = self.channels.image_event.subscribe();
+        let mut camera_recv = self.channels.camera_event.subscribe();
+
+        let mut queue = UploadQueue::open(self.config.queue_path.clone()).await?;
+        let mut originals = UploadQueue::open(self.config.queue_path.join("originals")).await?;
+
+        let preview_dir = self.config.queue_path.join("previews");
+        tokio::fs::create_dir_all(&preview_dir)
+            .await
+            .context("could not create preview directory")?;
+
+        let send_originals_when_idle = matches!(
+            self.config.preview,
+            None | Some(GroundServerPreviewConfig {
+                send_originals: SendOriginalsPolicy::Idle,
+                ..
+            })
+        );
+
+        let mut status = GroundServerStatus {
+            queue_length: queue.len(),
+            originals_queue_length: originals.len(),
+            ..Default::default()
+        };
+        let _ = self.status.send(Some(status.clone()));
+
+        let mut retry_delay = INITIAL_RETRY_DELAY;
+        let mut next_attempt = Instant::now();
 
-        let interrupt_fut = interrupt_recv.recv().fuse();
+        // uploads are sent from a separate task, so that images keep being
+        // received and queued while a slow upload is in progress
+        let mut in_flight: Option<InFlightUpload> = None;
+        let (result_tx, result_rx) = flume::unbounded();
 
-        futures::pin_mut!(interrupt_fut);
+        let heartbeat_endpoint = self.endpoint(UploadKind::Telemetry);
+        let heartbeat_interval = self.config.heartbeat_interval_ms.map(Duration::from_millis);
+        let mut heartbeat_timer =
+            tokio::time::interval(heartbeat_interval.unwrap_or(Duration::from_secs(1)));
+        let heartbeat_in_flight = Arc::new(AtomicBool::new(false));
+
+        if heartbeat_interval.is_some() && heartbeat_endpoint.is_none() {
+            warn!("this version of the ground server api does not accept telemetry heartbeats");
+        }
+
+        let heartbeat_url = heartbeat_endpoint
+            .filter(|_| heartbeat_interval.is_some())
+            .map(|endpoint| self.url(&endpoint))
+            .transpose()?;
 
         loop {
-            select! {
-                image_evt = image_recv.recv().fuse() => {
-                    if let Ok(ImageClientEvent {
-                        file,
-                        data,
-                        telemetry,
-                    }) = image_evt
-                    {
-                        debug!("image download detected, uploading file to ground server");
-
-                        let file_name = file
-                            .file_name()
-                            .map(OsStr::to_string_lossy)
-                            .expect("image has no filename");
-
-                        let telemetry_info = self.channels.pixhawk_telemetry.borrow().clone();
-
-                        if telemetry_info.is_none() {
-                            warn!("no telemetry data available for image capture")
+            tokio::select! {
+                image_evt = image_recv.recv() => {
+                    if let Err(RecvError::Lagged(skipped)) = image_evt {
+                        warn!("ground server client fell behind, {} images will not be uploaded", skipped);
+                        status.missed_images += skipped;
+                    }
+
+                    if let Ok(image_evt) = image_evt {
+                        debug!("image download detected, queueing upload to ground server");
+
+                        let preview = match (&self.config.preview, self.endpoint(UploadKind::Image)) {
+                            (Some(preview_config), Some(endpoint)) => {
+                                match self.preview_upload(&image_evt, endpoint, &preview_dir, preview_config).await {
+                                    Ok(upload) => Some(upload),
+                                    Err(err) => {
+                                        warn!("could not create preview, sending original image instead: {:?}", err);
+                                        None
+                                    }
+                                }
+                            }
+                            _ => None,
+                        };
+
+                        if let Some(preview) = preview {
+                            self.enqueue(&mut queue, Some(Ok(preview))).await;
+
+                            let original = self.endpoint(UploadKind::Original).map(|endpoint| {
+                                Ok::<_, anyhow::Error>(QueuedUpload {
+                                    endpoint,
+                                    body: self.image_body(&image_evt, false)?,
+                                    id: Some(image_evt.id()),
+                                    temporary_file: None,
+                                })
+                            });
+
+                            self.enqueue(&mut originals, original).await;
+                        } else {
+                            let upload = self.endpoint(UploadKind::Image).map(|endpoint| {
+                                Ok::<_, anyhow::Error>(QueuedUpload {
+                                    endpoint,
+                                    body: self.image_body(&image_evt, false)?,
+                                    id: Some(image_evt.id()),
+                                    temporary_file: None,
+                                })
+                            });
+
+                            self.enqueue(&mut queue, upload).await;
+                        }
+                    }
+                }
+                camera_evt = camera_recv.recv() => {
+                    if let Ok(CameraClientEvent::Capture { timestamp, .. }) = camera_evt {
+                        let telemetry = self
+                            .channels
+                            .telemetry_history
+                            .lock()
+                            .unwrap()
+                            .telemetry_at(timestamp)
+                            .or_else(|| *self.channels.pixhawk_telemetry.borrow());
+
+                        let upload = self.endpoint(UploadKind::Capture).map(|endpoint| {
+                            Ok::<_, anyhow::Error>(QueuedUpload {
+                                endpoint,
+                                body: self.schema.capture(timestamp, telemetry.as_ref())?,
+                                id: None,
+                                temporary_file: None,
+                            })
+                        });
+
+                        self.enqueue(&mut queue, upload).await;
+                    }
+                }
+                _ = heartbeat_timer.tick(), if heartbeat_url.is_some() => {
+                    let telemetry = match *self.channels.pixhawk_telemetry.borrow() {
+                        Some(telemetry) => telemetry,
+                        None => continue,
+                    };
+
+                    // heartbeats are not queued, because they are only useful
+                    // while they are current, and a slow heartbeat is skipped
+                    // rather than piling up behind the next one
+                    if heartbeat_in_flight.swap(true, Ordering::SeqCst) {
+                        continue;
+                    }
+
+                    let body = match self.schema.telemetry(&telemetry) {
+                        Ok(body) => body,
+                        Err(err) => {
+                            warn!("could not create telemetry heartbeat: {:?}", err);
+                            heartbeat_in_flight.store(false, Ordering::SeqCst);
+                            continue;
+                        }
+                    };
+
+                    let http = self.http.clone();
+                    let url = heartbeat_url.clone().unwrap();
+                    let heartbeat_in_flight = heartbeat_in_flight.clone();
+
+                    tokio::spawn(async move {
+                        match send(&http, url, &body).await {
+                            UploadResult::Sent => {}
+                            UploadResult::Failed(err, _) | UploadResult::Rejected(err) => {
+                                debug!("sending telemetry heartbeat failed: {:?}", err);
+                            }
                         }
 
-                        self.send_image(data.as_ref(), file_name.to_string(), telemetry).await?;
+                        heartbeat_in_flight.store(false, Ordering::SeqCst);
+                    });
+
+                    continue;
+                }
+                cmd = self.cmd.recv_async() => {
+                    if let Ok(cmd) = cmd {
+                        let result = match cmd.request() {
+                            GroundServerRequest::UploadOriginal { id } => {
+                                self.request_original(&mut queue, &mut originals, in_flight.as_ref(), id).await
+                            }
+                        };
+
+                        let _ = cmd.respond(result);
                     }
                 }
-                _ = interrupt_fut => {
+                _ = tokio::time::sleep_until(next_attempt), if in_flight.is_none() && (!queue.is_empty() || (send_originals_when_idle && !originals.is_empty())) => {
+                    // originals are only sent when there is nothing else to
+                    // send, so that they don't hold up previews of newer
+                    // images
+                    let (source, current) = if queue.is_empty() {
+                        (UploadSource::Originals, &originals)
+                    } else {
+                        (UploadSource::Queue, &queue)
+                    };
+                    let upload = current.front().expect("queue is not empty");
+
+                    in_flight = Some(InFlightUpload {
+                        source,
+                        id: upload.id.clone(),
+                        temporary_file: upload.temporary_file.clone(),
+                    });
+
+                    let http = self.http.clone();
+                    let url = self.url(&upload.endpoint);
+                    let body = upload.body.clone();
+                    let result_tx = result_tx.clone();
+
+                    tokio::spawn(async move {
+                        // uploads that were queued by an earlier run can have
+                        // endpoints that are no longer valid
+                        let result = match url {
+                            Ok(url) => send(&http, url, &body).await,
+                            Err(err) => UploadResult::Rejected(err),
+                        };
+
+                        let _ = result_tx.send(result);
+                    });
+                }
+                Ok(result) = result_rx.recv_async() => {
+                    let upload = in_flight.take().expect("an upload is in flight");
+
+                    match result {
+                        UploadResult::Sent => {
+                            status.uploaded += 1;
+                            status.consecutive_failures = 0;
+                            status.last_upload = Some(chrono::Local::now());
+                            retry_delay = INITIAL_RETRY_DELAY;
+
+                            debug!("uploaded to ground server");
+                        }
+                        UploadResult::Rejected(err) => {
+                            warn!("dropping upload: {:?}", err);
+
+                            status.failures += 1;
+                            status.consecutive_failures = 0;
+                            status.last_error = Some(format!("{:?}", err));
+                        }
+                        UploadResult::Failed(err, retry_after) => {
+                            // the ground server knows best when it will be
+                            // ready again
+                            let delay = retry_after.unwrap_or(retry_delay);
+
+                            warn!("upload failed, retrying in {:?}: {:?}", delay, err);
+
+                            status.failures += 1;
+                            status.consecutive_failures += 1;
+                            status.last_error = Some(format!("{:?}", err));
+
+                            next_attempt = Instant::now() + delay;
+                            retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+
+                            let _ = self.status.send(Some(status.clone()));
+                            continue;
+                        }
+                    }
+
+                    let current = match upload.source {
+                        UploadSource::Queue => &mut queue,
+                        UploadSource::Originals => &mut originals,
+                    };
+
+                    if let Err(err) = current.pop().await {
+                        warn!("{:?}", err);
+                    }
+
+                    if let Some(file) = upload.temporary_file {
+                        if let Err(err) = tokio::fs::remove_file(&file).await {
+                            warn!("could not remove '{}': {}", file.to_string_lossy(), err);
+                        }
+                    }
+                }
+                _ = interrupt_recv.recv() => {
                     break;
                 }
             }
+
+            status.queue_length = queue.len();
+            status.originals_queue_length = originals.len();
+            let _ = self.status.send(Some(status.clone()));
         }
 
         Ok(())
     }
 
-    /// Sends an image to the ground server.
-    pub async fn send_image(
+    /// Returns the path that uploads of the given kind are sent to, or `None`
+    /// if they should not be sent.
+    fn endpoint(&self, kind: UploadKind) -> Option<String> {
+        let GroundServerEndpoints {
+            image,
+            telemetry,
+            capture,
+            original,
+        } = &self.config.endpoints;
+
+        let endpoint = match kind {
+            UploadKind::Image => image,
+            UploadKind::Telemetry => telemetry,
+            UploadKind::Capture => capture,
+            UploadKind::Original => original,
+        };
+
+        endpoint
+            .clone()
+            .or_else(|| self.schema.endpoint(kind).map(str::to_owned))
+    }
+
+    fn url(&self, endpoint: &str) -> anyhow::Result<reqwest::Url> {
+        self.base_url
+            .join(endpoint)
+            .with_context(|| format!("invalid ground server endpoint {:?}", endpoint))
+    }
+
+    fn image_body(
         &self,
-        data: &[u8],
-        file_name: String,
-        telemetry: Option<Telemetry>,
-    ) -> anyhow::Result<()> {
-        let file_name = file_name.to_lowercase();
-
-        let mime_type = {
-            let file_ext = file_name.split(".").last();
-
-            match file_ext {
-                Some("jpg") | Some("jpeg") => "image/jpeg",
-                Some("mp4") => "video/mp4",
-                ext => {
-                    bail!(
-                        "unknown mime type for image file received from camera with extension {:?}",
-                        ext
-                    );
-                }
+        image_evt: &ImageClientEvent,
+        preview: bool,
+    ) -> anyhow::Result<UploadBody> {
+        let file_name = image_evt
+            .file
+            .file_name()
+            .context("image has no filename")?;
+
+        mime_type(&file_name.to_string_lossy())?;
+
+        self.schema.image(image_evt, preview)
+    }
+
+    /// Creates a preview of an image in `preview_dir` and returns an upload
+    /// for it. The preview is deleted once it has been sent.
+    async fn preview_upload(
+        &self,
+        image_evt: &ImageClientEvent,
+        endpoint: String,
+        preview_dir: &Path,
+        config: &GroundServerPreviewConfig,
+    ) -> anyhow::Result<QueuedUpload> {
+        let preview_file = preview_dir.join(format!("{}.jpg", image_evt.id()));
+
+        tokio::task::spawn_blocking({
+            let src = image_evt.file.clone();
+            let dest = preview_file.clone();
+            let config = config.clone();
+            move || preview::create(&src, &dest, &config)
+        })
+        .await??;
+
+        let preview_evt = ImageClientEvent {
+            file: preview_file.clone(),
+            ..image_evt.clone()
+        };
+
+        Ok(QueuedUpload {
+            endpoint,
+            body: self.image_body(&preview_evt, true)?,
+            id: Some(image_evt.id()),
+            temporary_file: Some(preview_file),
+        })
+    }
+
+    /// Moves the original image with the given id to the back of the main
+    /// queue, so that it is sent without waiting for the link to be idle.
+    async fn request_original(
+        &self,
+        queue: &mut UploadQueue,
+        originals: &mut UploadQueue,
+        in_flight: Option<&InFlightUpload>,
+        id: &str,
+    ) -> anyhow::Result<GroundServerResponse> {
+        // the original is already being sent, and has to stay at the front
+        // of its queue until the result comes back
+        if let Some(InFlightUpload {
+            source: UploadSource::Originals,
+            id: Some(in_flight_id),
+            ..
+        }) = in_flight
+        {
+            if in_flight_id == id {
+                return Ok(GroundServerResponse::Unit);
             }
+        }
+
+        match originals.take(id).await? {
+            Some(upload) => {
+                queue.push(upload).await?;
+                Ok(GroundServerResponse::Unit)
+            }
+            None => bail!("no original image with id '{}' is waiting to be sent", id),
+        }
+    }
+
+    /// Adds an upload to the queue, if there is one.
+    async fn enqueue(&self, queue: &mut UploadQueue, upload: Option<anyhow::Result<QueuedUpload>>) {
+        let result = match upload {
+            Some(Ok(upload)) => queue.push(upload).await,
+            Some(Err(err)) => Err(err),
+            None => return,
         };
 
-        let endpoint = self
-            .base_url
-            .join("/api/v1/image")
-            .expect("could not create image upload url");
-
-        let timestamp = chrono::Utc::now().timestamp_millis();
-
-        let json = if let Some(telemetry) = telemetry {
-            json!({
-                "timestamp": timestamp,
-                "imgMode": "fixed",
-                "fov": 60.0,
-                "telemetry": {
-                    "altitude": telemetry.position.altitude_rel,
-                    "planeYaw": telemetry.plane_attitude.yaw,
-                    "gps": {
-                        "longitude": telemetry.position.point.x(),
-                        "latitude": telemetry.position.point.y(),
-                    },
-                    "gimOrt": {
-                        "pitch": telemetry.gimbal_attitude.pitch,
-                        "roll": telemetry.gimbal_attitude.roll,
-                    }
+        if let Err(err) = result {
+            warn!("could not queue upload to ground server: {:?}", err);
+        }
+    }
+}
+
+/// Sends a request to the ground server.
+async fn send(http: &reqwest::Client, url: reqwest::Url, body: &UploadBody) -> UploadResult {
+    let request = match body {
+        UploadBody::Json { json } => http.post(url).json(json),
+        UploadBody::Multipart {
+            json_part,
+            json,
+            file_part,
+            file,
+        } => {
+            let file_name = match file.file_name().map(OsStr::to_string_lossy) {
+                Some(file_name) => file_name.to_lowercase(),
+                None => return UploadResult::Rejected(anyhow!("image has no filename")),
+            };
+
+            let mime_type = match mime_type(&file_name) {
+                Ok(mime_type) => mime_type,
+                Err(err) => return UploadResult::Rejected(err),
+            };
+
+            let data = match tokio::fs::read(file).await {
+                Ok(data) => data,
+                Err(err) => {
+                    return UploadResult::Rejected(
+                        anyhow::Error::from(err).context("could not read image file"),
+                    )
                 }
-            })
-        } else {
-            if cfg!(debug_assertions) {
-                warn!("no telemetry information available, uploading filler telemetry info");
-
-                json!({
-                    "timestamp": timestamp,
-                    "imgMode": "fixed",
-                    "fov": 60.0,
-                    "telemetry": {
-                        "altitude": 0.0,
-                        "planeYaw": 0.0,
-                        "gps": {
-                            "latitude": 0.0,
-                            "longitude": 0.0,
-                        },
-                        "gimOrt": {
-                            "pitch": 0.0,
-                            "roll": 0.0,
-                        }
-                    }
-                })
+            };
+
+            let form = reqwest::multipart::Form::new()
+                .part(
+                    json_part.clone(),
+                    reqwest::multipart::Part::text(json.to_string()),
+                )
+                .part(
+                    file_part.clone(),
+                    reqwest::multipart::Part::bytes(data)
+                        .file_name(file_name)
+                        .mime_str(mime_type)
+                        .expect("invalid mime type"),
+                );
+
+            http.post(url).multipart(form)
+        }
+    };
+
+    let res = match request.send().await {
+        Ok(res) => res,
+        Err(err) => {
+            return UploadResult::Failed(
+                anyhow::Error::from(err).context("could not reach ground server"),
+                None,
+            )
+        }
+    };
+
+    let status = res.status();
+    let retry_after = retry_after(&res);
+
+    match res.error_for_status() {
+        Ok(_) => UploadResult::Sent,
+        Err(err) => {
+            let err = anyhow::Error::from(err).context("upload to ground server failed");
+
+            // the ground server will give the same response to the same
+            // request, unless it's having problems of its own, or it timed
+            // out or was too busy to handle the request
+            let transient = status == reqwest::StatusCode::REQUEST_TIMEOUT
+                || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+
+            if status.is_client_error() && !transient {
+                UploadResult::Rejected(err)
             } else {
-                bail!("no telemetry information available, cannot upload to ground server");
+                UploadResult::Failed(err, retry_after)
             }
-        };
+        }
+    }
+}
 
-        let form = reqwest::multipart::Form::new()
-            .part("json", reqwest::multipart::Part::text(json.to_string()))
-            .part(
-                "files",
-                reqwest::multipart::Part::bytes(Vec::from(data))
-                    .file_name(file_name)
-                    .mime_str(mime_type)?,
-            );
+/// Reads the `Retry-After` header of a response, which is either a number of
+/// seconds or an HTTP date.
+fn retry_after(res: &reqwest::Response) -> Option<Duration> {
+    let value = res
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
 
-        let res = self.http.post(endpoint).multipart(form).send().await?;
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
 
-        res.error_for_status()
-            .context("uploading image and telemetry to ground server failed")?;
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
 
-        debug!("uploaded image and telemetry to ground server");
+    // a date in the past means that the request can be retried right away
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
 
-        Ok(())
+fn mime_type(file_name: &str) -> anyhow::Result<&'static str> {
+    let file_name = file_name.to_lowercase();
+    let file_ext = file_name.split('.').last();
+
+    match file_ext {
+        Some("jpg") | Some("jpeg") => Ok("image/jpeg"),
+        Some("mp4") => Ok("video/mp4"),
+        ext => {
+            bail!(
+                "unknown mime type for image file received from camera with extension {:?}",
+                ext
+            );
+        }
     }
 }
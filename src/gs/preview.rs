@@ -0,0 +1,30 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::Context;
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, GenericImageView};
+
+use crate::cli::config::GroundServerPreviewConfig;
+
+/// Writes a smaller, more heavily compressed copy of the JPEG image at `src`
+/// to `dest`. This is slow, so it should be called from a blocking task.
+pub fn create(src: &Path, dest: &Path, config: &GroundServerPreviewConfig) -> anyhow::Result<()> {
+    let image = image::open(src).context("could not decode image")?;
+
+    let (width, height) = image.dimensions();
+
+    // don't upscale images that are already smaller than the preview size
+    let image = if width > config.max_size || height > config.max_size {
+        image.resize(config.max_size, config.max_size, FilterType::Triangle)
+    } else {
+        image
+    };
+
+    let file = File::create(dest).context("could not create preview file")?;
+    let mut writer = BufWriter::new(file);
+
+    JpegEncoder::new_with_quality(&mut writer, config.quality)
+        .encode_image(&image)
+        .context("could not encode preview")?;
+
+    Ok(())
+}
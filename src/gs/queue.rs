@@ -0,0 +1,165 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::schema::UploadBody;
+
+/// A request that is waiting to be sent to the ground server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedUpload {
+    /// The path to send the request to, relative to the ground server's
+    /// address
+    pub endpoint: String,
+
+    pub body: UploadBody,
+
+    /// The name of the image that this upload belongs to, if any
+    #[serde(default)]
+    pub id: Option<String>,
+
+    /// A file that was created only for this upload, and is deleted once the
+    /// upload has been sent
+    #[serde(default)]
+    pub temporary_file: Option<PathBuf>,
+}
+
+/// A first-in, first-out queue of uploads that is mirrored to the disk, so
+/// that uploads which have not been sent when the plane system stops are
+/// resumed when it starts again.
+pub struct UploadQueue {
+    dir: PathBuf,
+    entries: VecDeque<(PathBuf, QueuedUpload)>,
+    next_id: u64,
+}
+
+impl UploadQueue {
+    /// Opens the queue stored in `dir`, creating it if it does not exist.
+    pub async fn open(dir: PathBuf) -> anyhow::Result<Self> {
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .context("could not create upload queue directory")?;
+
+        // entries are named after their position in the queue, so sorting
+        // them by name puts them in order
+        let mut paths = vec![];
+        let mut dir_entries = tokio::fs::read_dir(&dir)
+            .await
+            .context("could not read upload queue directory")?;
+
+        while let Some(entry) = dir_entries.next_entry().await? {
+            let path = entry.path();
+
+            if path.extension().map_or(false, |ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+
+        paths.sort();
+
+        let mut entries = VecDeque::new();
+        let mut next_id = 0;
+
+        for path in paths {
+            if let Some(id) = entry_id(&path) {
+                next_id = next_id.max(id + 1);
+            }
+
+            let upload = tokio::fs::read(&path)
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice(&data)?));
+
+            match upload {
+                Ok(upload) => entries.push_back((path, upload)),
+                Err(err) => warn!(
+                    "skipping invalid upload queue entry '{}': {}",
+                    path.to_string_lossy(),
+                    err
+                ),
+            }
+        }
+
+        if !entries.is_empty() {
+            info!("resuming {} queued uploads", entries.len());
+        }
+
+        Ok(UploadQueue {
+            dir,
+            entries,
+            next_id,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn front(&self) -> Option<&QueuedUpload> {
+        self.entries.front().map(|(_, upload)| upload)
+    }
+
+    /// Adds an upload to the back of the queue.
+    pub async fn push(&mut self, upload: QueuedUpload) -> anyhow::Result<()> {
+        let path = self.dir.join(format!("{:020}.json", self.next_id));
+        let temp_path = path.with_extension("tmp");
+
+        // write to a temporary file first so that a crash can't leave a
+        // partially written entry in the queue
+        let data = serde_json::to_vec(&upload).context("failed to serialize upload")?;
+
+        tokio::fs::write(&temp_path, data)
+            .await
+            .context("failed to write upload queue entry")?;
+        tokio::fs::rename(&temp_path, &path)
+            .await
+            .context("failed to write upload queue entry")?;
+
+        self.next_id += 1;
+        self.entries.push_back((path, upload));
+
+        Ok(())
+    }
+
+    /// Removes the upload at the front of the queue.
+    pub async fn pop(&mut self) -> anyhow::Result<()> {
+        if let Some((path, _)) = self.entries.pop_front() {
+            tokio::fs::remove_file(&path)
+                .await
+                .context("failed to remove upload queue entry")?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the first upload with the given id from the queue and returns
+    /// it, or returns `None` if there is no such upload.
+    pub async fn take(&mut self, id: &str) -> anyhow::Result<Option<QueuedUpload>> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(_, upload)| upload.id.as_deref() == Some(id));
+
+        let (path, upload) = match index.and_then(|index| self.entries.remove(index)) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        tokio::fs::remove_file(&path)
+            .await
+            .context("failed to remove upload queue entry")?;
+
+        Ok(Some(upload))
+    }
+}
+
+fn entry_id(path: &Path) -> Option<u64> {
+    path.file_stem()?.to_str()?.parse().ok()
+}
@@ -0,0 +1,152 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{image::ImageClientEvent, state::Telemetry};
+
+/// The kinds of data that can be sent to the ground server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadKind {
+    Image,
+    Telemetry,
+    Capture,
+
+    /// A full-resolution image whose preview has already been sent
+    Original,
+}
+
+/// The body of a request to the ground server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UploadBody {
+    Json {
+        json: serde_json::Value,
+    },
+    Multipart {
+        /// The name of the part that contains `json`
+        json_part: String,
+        json: serde_json::Value,
+
+        /// The name of the part that contains the contents of `file`
+        file_part: String,
+        file: PathBuf,
+    },
+}
+
+/// The layout of the requests that a version of the ground server's API
+/// accepts.
+pub trait UploadSchema: Send + Sync {
+    /// The path that uploads of this kind are sent to, relative to the ground
+    /// server's address, or `None` if this version of the API does not
+    /// accept them.
+    fn endpoint(&self, kind: UploadKind) -> Option<&'static str>;
+
+    /// `preview` is true if `image.file` is a preview of the original image.
+    fn image(&self, image: &ImageClientEvent, preview: bool) -> anyhow::Result<UploadBody>;
+
+    fn telemetry(&self, telemetry: &Telemetry) -> anyhow::Result<UploadBody>;
+
+    fn capture(
+        &self,
+        timestamp: chrono::DateTime<chrono::Local>,
+        telemetry: Option<&Telemetry>,
+    ) -> anyhow::Result<UploadBody>;
+}
+
+/// The version of the ground server's API that the plane system talks to.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroundServerApi {
+    V1,
+}
+
+impl Default for GroundServerApi {
+    fn default() -> Self {
+        GroundServerApi::V1
+    }
+}
+
+impl GroundServerApi {
+    pub fn schema(self) -> Box<dyn UploadSchema> {
+        match self {
+            GroundServerApi::V1 => Box::new(V1Schema),
+        }
+    }
+}
+
+/// The original API, which only accepts images. It can't tell previews apart
+/// from the original images, so originals are not sent to it.
+pub struct V1Schema;
+
+impl V1Schema {
+    fn telemetry_json(telemetry: Option<&Telemetry>) -> anyhow::Result<serde_json::Value> {
+        let telemetry = match telemetry {
+            Some(telemetry) => *telemetry,
+            None if cfg!(debug_assertions) => {
+                warn!("no telemetry information available, uploading filler telemetry info");
+                Telemetry::default()
+            }
+            None => bail!("no telemetry information available, cannot upload to ground server"),
+        };
+
+        Ok(json!({
+            "altitude": telemetry.position.altitude_rel,
+            "planeYaw": telemetry.plane_attitude.yaw,
+            "gps": {
+                "longitude": telemetry.position.point.x(),
+                "latitude": telemetry.position.point.y(),
+            },
+            "gimOrt": {
+                "pitch": telemetry.gimbal_attitude.pitch,
+                "roll": telemetry.gimbal_attitude.roll,
+            }
+        }))
+    }
+}
+
+impl UploadSchema for V1Schema {
+    fn endpoint(&self, kind: UploadKind) -> Option<&'static str> {
+        match kind {
+            UploadKind::Image => Some("/api/v1/image"),
+            UploadKind::Telemetry | UploadKind::Capture | UploadKind::Original => None,
+        }
+    }
+
+    fn image(&self, image: &ImageClientEvent, _preview: bool) -> anyhow::Result<UploadBody> {
+        Ok(UploadBody::Multipart {
+            json_part: "json".to_owned(),
+            json: json!({
+                "timestamp": chrono::Utc::now().timestamp_millis(),
+                "imgMode": "fixed",
+                "fov": image.intrinsics.hfov(),
+                "footprint": image.footprint,
+                "telemetry": Self::telemetry_json(image.telemetry.as_ref())?,
+            }),
+            file_part: "files".to_owned(),
+            file: image.file.clone(),
+        })
+    }
+
+    fn telemetry(&self, telemetry: &Telemetry) -> anyhow::Result<UploadBody> {
+        Ok(UploadBody::Json {
+            json: json!({
+                "timestamp": telemetry.timestamp.timestamp_millis(),
+                "telemetry": Self::telemetry_json(Some(telemetry))?,
+            }),
+        })
+    }
+
+    fn capture(
+        &self,
+        timestamp: chrono::DateTime<chrono::Local>,
+        telemetry: Option<&Telemetry>,
+    ) -> anyhow::Result<UploadBody> {
+        Ok(UploadBody::Json {
+            json: json!({
+                "timestamp": timestamp.timestamp_millis(),
+                "telemetry": Self::telemetry_json(telemetry)?,
+            }),
+        })
+    }
+}
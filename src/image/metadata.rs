@@ -0,0 +1,763 @@
+//! Embeds telemetry into JPEG images, as EXIF GPS tags and as the XMP tags
+//! that DJI drones write, since those are what most photogrammetry and GIS
+//! tools read.
+
+use std::convert::TryInto;
+
+use anyhow::Context;
+use chrono::{Datelike, Timelike};
+
+use crate::state::Telemetry;
+
+const MARKER_SOI: u8 = 0xD8;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_APP0: u8 = 0xE0;
+const MARKER_APP1: u8 = 0xE1;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+const TAG_GPS_INFO: u16 = 0x8825;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+#[derive(Debug, Clone, Copy)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn read_u16(self, data: &[u8], offset: usize) -> anyhow::Result<u16> {
+        let bytes: [u8; 2] = data
+            .get(offset..offset + 2)
+            .context("unexpected end of EXIF data")?
+            .try_into()?;
+
+        Ok(match self {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn read_u32(self, data: &[u8], offset: usize) -> anyhow::Result<u32> {
+        let bytes: [u8; 4] = data
+            .get(offset..offset + 4)
+            .context("unexpected end of EXIF data")?
+            .try_into()?;
+
+        Ok(match self {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn u16(self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::Little => value.to_le_bytes(),
+            ByteOrder::Big => value.to_be_bytes(),
+        }
+    }
+
+    fn u32(self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::Little => value.to_le_bytes(),
+            ByteOrder::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+enum EntryValue {
+    /// A value that is written inline if it fits, or after the IFD otherwise.
+    Data(Vec<u8>),
+
+    /// The value field of an entry copied from an existing IFD, which may be
+    /// an offset to data elsewhere in the TIFF structure.
+    Raw([u8; 4]),
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: EntryValue,
+}
+
+impl Entry {
+    fn ascii(tag: u16, value: &str) -> Self {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+
+        Entry {
+            tag,
+            kind: TYPE_ASCII,
+            count: data.len() as u32,
+            value: EntryValue::Data(data),
+        }
+    }
+
+    fn bytes(tag: u16, value: &[u8]) -> Self {
+        Entry {
+            tag,
+            kind: TYPE_BYTE,
+            count: value.len() as u32,
+            value: EntryValue::Data(value.to_vec()),
+        }
+    }
+
+    fn long(order: ByteOrder, tag: u16, value: u32) -> Self {
+        Entry {
+            tag,
+            kind: TYPE_LONG,
+            count: 1,
+            value: EntryValue::Data(order.u32(value).to_vec()),
+        }
+    }
+
+    fn rationals(order: ByteOrder, tag: u16, values: &[(u32, u32)]) -> Self {
+        let mut data = vec![];
+
+        for &(numerator, denominator) in values {
+            data.extend_from_slice(&order.u32(numerator));
+            data.extend_from_slice(&order.u32(denominator));
+        }
+
+        Entry {
+            tag,
+            kind: TYPE_RATIONAL,
+            count: values.len() as u32,
+            value: EntryValue::Data(data),
+        }
+    }
+}
+
+/// Appends an IFD to `tiff` and returns its offset. Offsets in TIFF
+/// structures are relative to the start of the TIFF header, which is the
+/// start of `tiff`.
+fn write_ifd(tiff: &mut Vec<u8>, order: ByteOrder, entries: &[Entry], next_ifd: u32) -> u32 {
+    // IFDs must start on a word boundary
+    if tiff.len() % 2 != 0 {
+        tiff.push(0);
+    }
+
+    let ifd_offset = tiff.len();
+    let mut data_offset = ifd_offset + 2 + entries.len() * 12 + 4;
+    let mut data_area = vec![];
+
+    tiff.extend_from_slice(&order.u16(entries.len() as u16));
+
+    for entry in entries {
+        tiff.extend_from_slice(&order.u16(entry.tag));
+        tiff.extend_from_slice(&order.u16(entry.kind));
+        tiff.extend_from_slice(&order.u32(entry.count));
+
+        match &entry.value {
+            EntryValue::Raw(raw) => tiff.extend_from_slice(raw),
+            EntryValue::Data(data) if data.len() <= 4 => {
+                let mut inline = [0; 4];
+                inline[..data.len()].copy_from_slice(data);
+                tiff.extend_from_slice(&inline);
+            }
+            EntryValue::Data(data) => {
+                tiff.extend_from_slice(&order.u32(data_offset as u32));
+                data_area.extend_from_slice(data);
+
+                if data.len() % 2 != 0 {
+                    data_area.push(0);
+                }
+
+                data_offset += data.len() + data.len() % 2;
+            }
+        }
+    }
+
+    tiff.extend_from_slice(&order.u32(next_ifd));
+    tiff.extend_from_slice(&data_area);
+
+    ifd_offset as u32
+}
+
+/// Splits a coordinate in degrees into degrees, minutes and seconds.
+fn dms(value: f64) -> [(u32, u32); 3] {
+    let value = value.abs();
+    let degrees = value.trunc();
+    let minutes = ((value - degrees) * 60.).trunc();
+    let seconds = ((value - degrees) * 60. - minutes) * 60.;
+
+    [
+        (degrees as u32, 1),
+        (minutes as u32, 1),
+        ((seconds * 10000.).round() as u32, 10000),
+    ]
+}
+
+fn gps_entries(
+    order: ByteOrder,
+    telemetry: &Telemetry,
+    timestamp: chrono::DateTime<chrono::Local>,
+) -> Vec<Entry> {
+    let latitude = telemetry.position.point.y() as f64;
+    let longitude = telemetry.position.point.x() as f64;
+    let altitude = telemetry.position.altitude_msl as f64;
+    let timestamp = timestamp.with_timezone(&chrono::Utc);
+
+    vec![
+        Entry::bytes(0x0000, &[2, 3, 0, 0]),
+        Entry::ascii(0x0001, if latitude < 0. { "S" } else { "N" }),
+        Entry::rationals(order, 0x0002, &dms(latitude)),
+        Entry::ascii(0x0003, if longitude < 0. { "W" } else { "E" }),
+        Entry::rationals(order, 0x0004, &dms(longitude)),
+        Entry::bytes(0x0005, &[(altitude < 0.) as u8]),
+        Entry::rationals(
+            order,
+            0x0006,
+            &[((altitude.abs() * 1000.).round() as u32, 1000)],
+        ),
+        Entry::rationals(
+            order,
+            0x0007,
+            &[
+                (timestamp.hour(), 1),
+                (timestamp.minute(), 1),
+                (
+                    timestamp.second() * 1000 + timestamp.nanosecond() / 1_000_000 % 1000,
+                    1000,
+                ),
+            ],
+        ),
+        Entry::ascii(
+            0x001D,
+            &format!(
+                "{:04}:{:02}:{:02}",
+                timestamp.year(),
+                timestamp.month(),
+                timestamp.day()
+            ),
+        ),
+    ]
+}
+
+/// Adds a GPS IFD to an existing EXIF TIFF structure. The GPS IFD and a copy
+/// of IFD0 that points to it are appended to the end, so that none of the
+/// existing offsets need to change.
+fn add_gps_to_exif(
+    tiff: &[u8],
+    telemetry: &Telemetry,
+    timestamp: chrono::DateTime<chrono::Local>,
+) -> anyhow::Result<Vec<u8>> {
+    let order = match tiff.get(0..2) {
+        Some(b"II") => ByteOrder::Little,
+        Some(b"MM") => ByteOrder::Big,
+        _ => bail!("invalid TIFF header in EXIF data"),
+    };
+
+    let ifd0_offset = order.read_u32(tiff, 4)? as usize;
+    let entry_count = order.read_u16(tiff, ifd0_offset)? as usize;
+
+    let mut entries = vec![];
+
+    for i in 0..entry_count {
+        let offset = ifd0_offset + 2 + i * 12;
+        let tag = order.read_u16(tiff, offset)?;
+
+        if tag == TAG_GPS_INFO {
+            continue;
+        }
+
+        entries.push(Entry {
+            tag,
+            kind: order.read_u16(tiff, offset + 2)?,
+            count: order.read_u32(tiff, offset + 4)?,
+            value: EntryValue::Raw(
+                tiff.get(offset + 8..offset + 12)
+                    .context("unexpected end of EXIF data")?
+                    .try_into()?,
+            ),
+        });
+    }
+
+    let next_ifd = order.read_u32(tiff, ifd0_offset + 2 + entry_count * 12)?;
+
+    let mut tiff = tiff.to_vec();
+    let gps_offset = write_ifd(
+        &mut tiff,
+        order,
+        &gps_entries(order, telemetry, timestamp),
+        0,
+    );
+
+    entries.push(Entry::long(order, TAG_GPS_INFO, gps_offset));
+    entries.sort_by_key(|entry| entry.tag);
+
+    let ifd0_offset = write_ifd(&mut tiff, order, &entries, next_ifd);
+    tiff[4..8].copy_from_slice(&order.u32(ifd0_offset));
+
+    Ok(tiff)
+}
+
+/// Creates an EXIF TIFF structure that only contains GPS tags.
+fn new_exif(telemetry: &Telemetry, timestamp: chrono::DateTime<chrono::Local>) -> Vec<u8> {
+    let order = ByteOrder::Little;
+
+    // header, followed by an empty IFD0 that add_gps_to_exif will replace
+    let mut tiff = b"II\x2A\x00".to_vec();
+    tiff.extend_from_slice(&order.u32(8));
+    write_ifd(&mut tiff, order, &[], 0);
+
+    add_gps_to_exif(&tiff, telemetry, timestamp).expect("generated EXIF data is invalid")
+}
+
+/// Wraps an angle in degrees to the range (-180, 180].
+fn wrap_degrees(angle: f32) -> f32 {
+    let angle = angle.rem_euclid(360.);
+
+    if angle > 180. {
+        angle - 360.
+    } else {
+        angle
+    }
+}
+
+fn xmp(telemetry: &Telemetry) -> String {
+    // the camera points straight down when the gimbal is level, but DJI
+    // measures gimbal pitch from the horizon, and gimbal yaw from north
+    // rather than from the nose of the plane
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "  <rdf:Description rdf:about=\"\"\n",
+            "    xmlns:drone-dji=\"http://www.dji.com/drone-dji/1.0/\"\n",
+            "    drone-dji:GpsLatitude=\"{:.8}\"\n",
+            "    drone-dji:GpsLongitude=\"{:.8}\"\n",
+            "    drone-dji:AbsoluteAltitude=\"{:+.3}\"\n",
+            "    drone-dji:RelativeAltitude=\"{:+.3}\"\n",
+            "    drone-dji:GimbalRollDegree=\"{:+.2}\"\n",
+            "    drone-dji:GimbalYawDegree=\"{:+.2}\"\n",
+            "    drone-dji:GimbalPitchDegree=\"{:+.2}\"\n",
+            "    drone-dji:FlightRollDegree=\"{:+.2}\"\n",
+            "    drone-dji:FlightYawDegree=\"{:+.2}\"\n",
+            "    drone-dji:FlightPitchDegree=\"{:+.2}\"/>\n",
+            " </rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>",
+        ),
+        telemetry.position.point.y(),
+        telemetry.position.point.x(),
+        telemetry.position.altitude_msl,
+        telemetry.position.altitude_rel,
+        telemetry.gimbal_attitude.roll,
+        wrap_degrees(telemetry.plane_attitude.yaw + telemetry.gimbal_attitude.yaw),
+        telemetry.gimbal_attitude.pitch - 90.,
+        telemetry.plane_attitude.roll,
+        telemetry.plane_attitude.yaw,
+        telemetry.plane_attitude.pitch,
+    )
+}
+
+fn write_segment(
+    image: &mut Vec<u8>,
+    marker: u8,
+    header: &[u8],
+    payload: &[u8],
+) -> anyhow::Result<()> {
+    // the length includes itself but not the marker
+    let length = 2 + header.len() + payload.len();
+
+    if length > u16::MAX as usize {
+        bail!("metadata is too large to fit in a JPEG segment");
+    }
+
+    image.extend_from_slice(&[0xFF, marker]);
+    image.extend_from_slice(&(length as u16).to_be_bytes());
+    image.extend_from_slice(header);
+    image.extend_from_slice(payload);
+
+    Ok(())
+}
+
+/// Returns a copy of a JPEG image with the location and attitude of the plane
+/// embedded in it. GPS tags are added to the camera's EXIF data, and any
+/// existing XMP data is replaced.
+pub fn embed(
+    image: &[u8],
+    telemetry: &Telemetry,
+    timestamp: chrono::DateTime<chrono::Local>,
+) -> anyhow::Result<Vec<u8>> {
+    if !image.starts_with(&[0xFF, MARKER_SOI]) {
+        bail!("image is not a JPEG");
+    }
+
+    let mut output = Vec::with_capacity(image.len() + 4096);
+    output.extend_from_slice(&image[0..2]);
+
+    let mut exif = None;
+    let mut other_segments = vec![];
+    let mut offset = 2;
+
+    // read the segments before the image data, keeping all of them except
+    // for the EXIF and XMP segments
+    loop {
+        let marker = match image.get(offset..offset + 2) {
+            Some(&[0xFF, marker]) => marker,
+            _ => bail!("invalid JPEG segment at offset {}", offset),
+        };
+
+        if marker == MARKER_SOS {
+            break;
+        }
+
+        let length = image
+            .get(offset + 2..offset + 4)
+            .map(|length| u16::from_be_bytes([length[0], length[1]]) as usize)
+            .context("unexpected end of JPEG")?;
+
+        if length < 2 {
+            bail!("invalid JPEG segment length at offset {}", offset);
+        }
+
+        let segment = image
+            .get(offset..offset + 2 + length)
+            .context("unexpected end of JPEG")?;
+        let payload = &segment[4..];
+
+        let is_exif = marker == MARKER_APP1 && payload.starts_with(EXIF_HEADER);
+        let is_xmp = marker == MARKER_APP1 && payload.starts_with(XMP_HEADER);
+
+        if is_exif {
+            exif = Some(&payload[EXIF_HEADER.len()..]);
+        } else if !is_xmp {
+            other_segments.push((marker, segment));
+        }
+
+        offset += 2 + length;
+    }
+
+    let exif = match exif {
+        Some(exif) => add_gps_to_exif(exif, telemetry, timestamp)
+            .context("failed to add GPS tags to EXIF data")?,
+        None => new_exif(telemetry, timestamp),
+    };
+
+    // JFIF requires its APP0 segment to come first
+    let app0_count = other_segments
+        .iter()
+        .take_while(|(marker, _)| *marker == MARKER_APP0)
+        .count();
+
+    for (_, segment) in &other_segments[..app0_count] {
+        output.extend_from_slice(segment);
+    }
+
+    write_segment(&mut output, MARKER_APP1, EXIF_HEADER, &exif)?;
+    write_segment(
+        &mut output,
+        MARKER_APP1,
+        XMP_HEADER,
+        xmp(telemetry).as_bytes(),
+    )?;
+
+    for (_, segment) in &other_segments[app0_count..] {
+        output.extend_from_slice(segment);
+    }
+
+    output.extend_from_slice(&image[offset..]);
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPE_SHORT: u16 = 3;
+
+    const TAG_MAKE: u16 = 0x010F;
+    const TAG_ORIENTATION: u16 = 0x0112;
+    const TAG_JPEG_INTERCHANGE_FORMAT: u16 = 0x0201;
+    const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+    const TAG_GPS_LATITUDE: u16 = 0x0002;
+    const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+
+    const JFIF: &[u8] = &[0xFF, MARKER_APP0, 0x00, 0x07, b'J', b'F', b'I', b'F', 0x00];
+
+    /// A quantization table, which stands in for the segments that come after
+    /// the metadata.
+    const DQT: &[u8] = &[0xFF, 0xDB, 0x00, 0x04, 0xAA, 0xBB];
+
+    /// The start of scan and the image data, which must be copied unchanged.
+    const SCAN: &[u8] = &[
+        0xFF, MARKER_SOS, 0x00, 0x04, 0x01, 0x02, 0x12, 0x34, 0xFF, 0xD9,
+    ];
+
+    /// An entry as it was read back from an IFD.
+    struct IfdEntry {
+        tag: u16,
+        kind: u16,
+        count: u32,
+        value: [u8; 4],
+    }
+
+    struct Exif {
+        tiff: Vec<u8>,
+        order: ByteOrder,
+        ifd0: Vec<IfdEntry>,
+        next_ifd: u32,
+    }
+
+    fn telemetry() -> Telemetry {
+        let mut telemetry = Telemetry::default();
+        telemetry.position.point = geo::Point::new(-151.2153, -33.8568);
+        telemetry.position.altitude_msl = 120.5;
+        telemetry
+    }
+
+    fn jpeg(segments: &[&[u8]]) -> Vec<u8> {
+        let mut image = vec![0xFF, MARKER_SOI];
+
+        for segment in segments {
+            image.extend_from_slice(segment);
+        }
+
+        image.extend_from_slice(SCAN);
+        image
+    }
+
+    fn app1(header: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![];
+        write_segment(&mut segment, MARKER_APP1, header, payload).unwrap();
+        segment
+    }
+
+    /// Splits a JPEG into the markers and payloads of the segments before the
+    /// image data, and the image data.
+    fn segments(image: &[u8]) -> (Vec<(u8, &[u8])>, &[u8]) {
+        assert_eq!(&image[0..2], &[0xFF, MARKER_SOI]);
+
+        let mut segments = vec![];
+        let mut offset = 2;
+
+        while image[offset + 1] != MARKER_SOS {
+            assert_eq!(image[offset], 0xFF);
+
+            let length = u16::from_be_bytes([image[offset + 2], image[offset + 3]]) as usize;
+            segments.push((image[offset + 1], &image[offset + 4..offset + 2 + length]));
+            offset += 2 + length;
+        }
+
+        (segments, &image[offset..])
+    }
+
+    fn read_ifd(tiff: &[u8], order: ByteOrder, offset: u32) -> (Vec<IfdEntry>, u32) {
+        let offset = offset as usize;
+        let count = order.read_u16(tiff, offset).unwrap() as usize;
+
+        let entries = (0..count)
+            .map(|i| {
+                let offset = offset + 2 + i * 12;
+
+                IfdEntry {
+                    tag: order.read_u16(tiff, offset).unwrap(),
+                    kind: order.read_u16(tiff, offset + 2).unwrap(),
+                    count: order.read_u32(tiff, offset + 4).unwrap(),
+                    value: tiff[offset + 8..offset + 12].try_into().unwrap(),
+                }
+            })
+            .collect();
+
+        (
+            entries,
+            order.read_u32(tiff, offset + 2 + count * 12).unwrap(),
+        )
+    }
+
+    fn find(entries: &[IfdEntry], tag: u16) -> &IfdEntry {
+        entries
+            .iter()
+            .find(|entry| entry.tag == tag)
+            .unwrap_or_else(|| panic!("missing tag {:#06x}", tag))
+    }
+
+    /// Reads the data that an entry's value field points to.
+    fn data<'a>(exif: &'a Exif, entry: &IfdEntry, len: usize) -> &'a [u8] {
+        let offset = exif.order.read_u32(&entry.value, 0).unwrap() as usize;
+        &exif.tiff[offset..offset + len]
+    }
+
+    /// Finds the EXIF data in `image` and checks that its GPS IFD holds the
+    /// location from `telemetry()`.
+    fn read_exif(image: &[u8]) -> Exif {
+        let (segments, _) = segments(image);
+        let exif = segments
+            .iter()
+            .filter(|(marker, payload)| *marker == MARKER_APP1 && payload.starts_with(EXIF_HEADER))
+            .collect::<Vec<_>>();
+
+        assert_eq!(exif.len(), 1, "expected exactly one EXIF segment");
+
+        let tiff = exif[0].1[EXIF_HEADER.len()..].to_vec();
+        let order = match &tiff[0..2] {
+            b"II" => ByteOrder::Little,
+            b"MM" => ByteOrder::Big,
+            _ => panic!("invalid TIFF header"),
+        };
+
+        assert_eq!(order.read_u16(&tiff, 2).unwrap(), 42);
+
+        let (ifd0, next_ifd) = read_ifd(&tiff, order, order.read_u32(&tiff, 4).unwrap());
+        assert!(
+            ifd0.windows(2).all(|w| w[0].tag < w[1].tag),
+            "IFD0 is not sorted"
+        );
+
+        let exif = Exif {
+            tiff,
+            order,
+            ifd0,
+            next_ifd,
+        };
+
+        let gps_offset = order
+            .read_u32(&find(&exif.ifd0, TAG_GPS_INFO).value, 0)
+            .unwrap();
+        let (gps, _) = read_ifd(&exif.tiff, order, gps_offset);
+
+        assert_eq!(&find(&gps, TAG_GPS_LATITUDE_REF).value[..2], b"S\0");
+        assert_eq!(&find(&gps, TAG_GPS_LONGITUDE_REF).value[..2], b"W\0");
+
+        let latitude = find(&gps, TAG_GPS_LATITUDE);
+        assert_eq!((latitude.kind, latitude.count), (TYPE_RATIONAL, 3));
+
+        let latitude = data(&exif, latitude, 24);
+        let latitude = (0..6)
+            .map(|i| order.read_u32(latitude, i * 4).unwrap())
+            .collect::<Vec<_>>();
+
+        // the latitude is stored as an f32, which is 33.8568000793457
+        assert_eq!(latitude, [33, 1, 51, 1, 244803, 10000]);
+
+        exif
+    }
+
+    /// Creates EXIF data like a camera writes, with a string that is stored
+    /// after IFD0, a value that is stored inline, a GPS IFD pointer that has
+    /// to be replaced, and a second IFD for the thumbnail.
+    fn camera_exif(order: ByteOrder) -> Vec<u8> {
+        let mut tiff = match order {
+            ByteOrder::Little => b"II\x2A\x00".to_vec(),
+            ByteOrder::Big => b"MM\x00\x2A".to_vec(),
+        };
+        tiff.extend_from_slice(&order.u32(8));
+
+        let ifd0 = [
+            Entry::ascii(TAG_MAKE, "Sony"),
+            Entry {
+                tag: TAG_ORIENTATION,
+                kind: TYPE_SHORT,
+                count: 1,
+                value: EntryValue::Data(order.u16(6).to_vec()),
+            },
+            Entry::long(order, TAG_GPS_INFO, 0),
+        ];
+
+        // 8 byte header, 42 byte IFD0 and 6 bytes for the make
+        assert_eq!(write_ifd(&mut tiff, order, &ifd0, 56), 8);
+
+        let ifd1 = [Entry::long(order, TAG_JPEG_INTERCHANGE_FORMAT, 1000)];
+        assert_eq!(write_ifd(&mut tiff, order, &ifd1, 0), 56);
+
+        tiff
+    }
+
+    fn keeps_existing_exif(order: ByteOrder) {
+        let old_xmp = app1(XMP_HEADER, b"<x:xmpmeta/>");
+        let image = jpeg(&[JFIF, &app1(EXIF_HEADER, &camera_exif(order)), &old_xmp, DQT]);
+
+        let output = embed(&image, &telemetry(), chrono::Local::now()).unwrap();
+        let exif = read_exif(&output);
+
+        let tags = exif.ifd0.iter().map(|entry| entry.tag).collect::<Vec<_>>();
+        assert_eq!(tags, [TAG_MAKE, TAG_ORIENTATION, TAG_GPS_INFO]);
+
+        let make = find(&exif.ifd0, TAG_MAKE);
+        assert_eq!(make.count, 5);
+        assert_eq!(data(&exif, make, 5), b"Sony\0");
+
+        let orientation = find(&exif.ifd0, TAG_ORIENTATION);
+        assert_eq!(order.read_u16(&orientation.value, 0).unwrap(), 6);
+
+        // the thumbnail IFD is still linked from IFD0
+        assert_eq!(exif.next_ifd, 56);
+        let (ifd1, _) = read_ifd(&exif.tiff, order, exif.next_ifd);
+        let thumbnail = find(&ifd1, TAG_JPEG_INTERCHANGE_FORMAT);
+        assert_eq!(order.read_u32(&thumbnail.value, 0).unwrap(), 1000);
+
+        // the old XMP data is replaced
+        let (segments, scan) = segments(&output);
+        let xmp = segments
+            .iter()
+            .filter(|(_, payload)| payload.starts_with(XMP_HEADER))
+            .collect::<Vec<_>>();
+        assert_eq!(xmp.len(), 1);
+        assert_ne!(&xmp[0].1[XMP_HEADER.len()..], b"<x:xmpmeta/>");
+
+        assert_eq!(segments.last().unwrap().1, &DQT[4..]);
+        assert_eq!(scan, SCAN);
+    }
+
+    #[test]
+    fn adds_exif_to_jpeg_without_exif() {
+        let image = jpeg(&[JFIF, DQT]);
+
+        let output = embed(&image, &telemetry(), chrono::Local::now()).unwrap();
+        let exif = read_exif(&output);
+
+        assert_eq!(exif.ifd0.len(), 1);
+        assert_eq!(exif.next_ifd, 0);
+
+        let (segments, scan) = segments(&output);
+        let markers = segments
+            .iter()
+            .map(|(marker, _)| *marker)
+            .collect::<Vec<_>>();
+
+        // the JFIF segment has to stay first
+        assert_eq!(markers, [MARKER_APP0, MARKER_APP1, MARKER_APP1, 0xDB]);
+        assert_eq!(segments[0].1, &JFIF[4..]);
+        assert!(segments[2].1.starts_with(XMP_HEADER));
+        assert_eq!(segments[3].1, &DQT[4..]);
+        assert_eq!(scan, SCAN);
+    }
+
+    #[test]
+    fn keeps_existing_little_endian_exif() {
+        keeps_existing_exif(ByteOrder::Little);
+    }
+
+    #[test]
+    fn keeps_existing_big_endian_exif() {
+        keeps_existing_exif(ByteOrder::Big);
+    }
+
+    #[test]
+    fn dms_of_negative_coordinates() {
+        assert_eq!(dms(-33.8568), [(33, 1), (51, 1), (244800, 10000)]);
+        assert_eq!(dms(-151.2153), [(151, 1), (12, 1), (550800, 10000)]);
+    }
+
+    #[test]
+    fn gimbal_yaw_is_relative_to_north() {
+        let mut telemetry = telemetry();
+        telemetry.plane_attitude.yaw = 170.;
+        telemetry.gimbal_attitude.yaw = 30.;
+
+        let xmp = xmp(&telemetry);
+
+        assert!(xmp.contains("drone-dji:GimbalYawDegree=\"-160.00\""));
+        assert!(xmp.contains("drone-dji:FlightYawDegree=\"+170.00\""));
+    }
+}
@@ -1,3 +1,5 @@
+mod metadata;
+
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
@@ -5,24 +7,54 @@ use std::{
 
 use anyhow::Context;
 use futures::{select, FutureExt};
+use serde::Serialize;
 use tokio::{fs::File, io::AsyncWriteExt};
 
-#[cfg(feature = "csb")]
-use crate::camera::main::csb;
-
 use crate::{
-    camera::main::CameraClientEvent,
+    camera::main::{CameraClientEvent, LensState},
     cli::config::ImageConfig,
-    state::{Point3D, Telemetry},
-    util::ISO_8601_FORMAT,
+    geometry::{self, CameraIntrinsics, Footprint},
+    state::Telemetry,
     Channels,
 };
 
 #[derive(Clone, Debug)]
 pub struct ImageClientEvent {
-    pub data: Arc<Vec<u8>>,
     pub file: PathBuf,
+
+    /// Time at which the camera reported the capture of this image, which is
+    /// the same as the timestamp of the corresponding capture event
+    pub cc_timestamp: Option<chrono::DateTime<chrono::Local>>,
+
     pub telemetry: Option<Telemetry>,
+    pub intrinsics: CameraIntrinsics,
+    pub footprint: Option<Footprint>,
+}
+
+impl ImageClientEvent {
+    /// The name of the image without its extension, which identifies it to the
+    /// ground server.
+    pub fn id(&self) -> String {
+        self.file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// The information that is saved alongside each image.
+#[derive(Clone, Debug, Serialize)]
+struct ImageMetadata {
+    pixhawk_telemetry: Option<Telemetry>,
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    capture_timestamp: Option<chrono::DateTime<chrono::Local>>,
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    csb_timestamp: Option<chrono::DateTime<chrono::Local>>,
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    cc_timestamp: Option<chrono::DateTime<chrono::Local>>,
+    lens: Option<LensState>,
+    intrinsics: CameraIntrinsics,
+    footprint: Option<Footprint>,
 }
 
 pub async fn run(channels: Arc<Channels>, config: ImageConfig) -> anyhow::Result<()> {
@@ -40,59 +72,102 @@ pub async fn run(channels: Arc<Channels>, config: ImageConfig) -> anyhow::Result
         warn!("could not create image save directory: {}", err);
     }
 
+    // time of the most recent capture whose image has not been downloaded yet
+    let mut last_capture_timestamp = None;
+
     loop {
         select! {
             camera_evt = camera_recv.recv().fuse() => {
                 if let Ok(camera_evt) = camera_evt {
                     match camera_evt {
-                        CameraClientEvent::Download { image_name, image_data, cc_timestamp, .. } => {
+                        CameraClientEvent::Capture { timestamp, .. } => {
+                            last_capture_timestamp = Some(timestamp);
+                        }
+                        CameraClientEvent::Download { image_name, image_data, cc_timestamp, lens } => {
                             debug!("image download detected, uploading file to ground server");
 
-                            let pixhawk_telemetry = channels.pixhawk_telemetry.borrow().clone();
-
-                            if pixhawk_telemetry.is_none() {
-                                warn!("no pixhawk telemetry data available for image capture")
-                            }
-
                             #[cfg(feature = "csb")]
-                            let (csb_timestamp, offset_position) = {
+                            let csb_timestamp = {
                                 let csb_timestamp = channels.csb_telemetry.borrow().clone().map(|t| t.timestamp);
 
                                 if csb_timestamp.is_none() {
                                     warn!("no csb telemetry data available for image capture")
                                 }
 
-                                let offset_position = if let (Some(pixhawk_telemetry), Some(csb_timestamp)) = (&pixhawk_telemetry, &csb_timestamp) {
-                                    // velocity in meters per second east and north
-                                    let (vx, vy, vz) = pixhawk_telemetry.velocity;
-                                    let delay = *csb_timestamp - pixhawk_telemetry.timestamp;
-                                    let delay_seconds = delay.num_milliseconds() as f32 / 1000.;
-
-                                    // angle we are traveling at
-                                    let heading = pixhawk_telemetry.plane_attitude.yaw;
-
-                                    // distance traveled since we received gps from pixhawk
-                                    let distance_xy = f32::sqrt(vx * vx + vy * vy) * delay_seconds;
-                                    let offset_coords = pixhawk_telemetry.position.point.haversine_destination(heading, distance_xy);
-                                    let offset_altitude_rel = pixhawk_telemetry.position.altitude_rel + vz * delay_seconds;
-                                    let offset_altitude_msl = pixhawk_telemetry.position.altitude_msl + vz * delay_seconds;
-
-                                    Some(Point3D {
-                                        point: offset_coords,
-                                        altitude_msl: offset_altitude_msl,
-                                        altitude_rel: offset_altitude_rel,
-                                    })
-                                } else {
-                                    None
-                                };
-
-                                (csb_timestamp, offset_position)
+                                csb_timestamp
                             };
 
                             #[cfg(not(feature = "csb"))]
-                            let (csb_timestamp, offset_position) = (None, None);
+                            let csb_timestamp = None;
+
+                            // the current-sensing board sees the shutter fire,
+                            // so its timestamp is the most accurate one
+                            let capture_timestamp = csb_timestamp
+                                .or(cc_timestamp)
+                                .or_else(|| last_capture_timestamp.take());
+
+                            let pixhawk_telemetry = match capture_timestamp {
+                                Some(capture_timestamp) => {
+                                    let telemetry = channels.telemetry_history.lock().unwrap().telemetry_at(capture_timestamp);
 
-                            let image_filename = match save(&image_save_dir, &image_name, &image_data, &pixhawk_telemetry, offset_position, csb_timestamp, cc_timestamp).await {
+                                    if telemetry.is_none() {
+                                        warn!("no telemetry history available at time of image capture, using latest telemetry");
+                                    }
+
+                                    telemetry.or_else(|| *channels.pixhawk_telemetry.borrow())
+                                }
+                                None => {
+                                    warn!("capture time of image is unknown, using latest telemetry");
+                                    *channels.pixhawk_telemetry.borrow()
+                                }
+                            };
+
+                            if pixhawk_telemetry.is_none() {
+                                warn!("no pixhawk telemetry data available for image capture")
+                            }
+
+                            let intrinsics = match lens {
+                                Some(lens) => lens.intrinsics(),
+                                None => {
+                                    warn!("zoom of camera is unknown, assuming lens is zoomed out");
+                                    LensState::default().intrinsics()
+                                }
+                            };
+
+                            let footprint = pixhawk_telemetry
+                                .as_ref()
+                                .and_then(|telemetry| geometry::footprint(telemetry, &intrinsics));
+
+                            let is_jpeg = Path::new(&image_name)
+                                .extension()
+                                .map_or(false, |ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"));
+
+                            let image_data = match &pixhawk_telemetry {
+                                Some(telemetry) if config.embed_metadata && is_jpeg => {
+                                    let timestamp = capture_timestamp.unwrap_or(telemetry.timestamp);
+
+                                    match metadata::embed(&image_data, telemetry, timestamp) {
+                                        Ok(image_data) => Arc::new(image_data),
+                                        Err(err) => {
+                                            warn!("could not embed metadata in image: {:?}", err);
+                                            image_data
+                                        }
+                                    }
+                                }
+                                _ => image_data,
+                            };
+
+                            let metadata = ImageMetadata {
+                                pixhawk_telemetry,
+                                capture_timestamp,
+                                csb_timestamp,
+                                cc_timestamp,
+                                lens,
+                                intrinsics,
+                                footprint,
+                            };
+
+                            let image_filename = match save(&image_save_dir, &image_name, &image_data, &metadata).await {
                                 Ok(image_filename) => image_filename,
                                 Err(err) => {
                                   warn!("failed to download image: {}", err);
@@ -101,9 +176,11 @@ pub async fn run(channels: Arc<Channels>, config: ImageConfig) -> anyhow::Result
                             };
 
                             let _ = channels.image_event.send(ImageClientEvent {
-                              data: image_data,
                               file: image_filename,
-                              telemetry: pixhawk_telemetry
+                              cc_timestamp,
+                              telemetry: pixhawk_telemetry,
+                              intrinsics,
+                              footprint,
                             });
                         }
                         _ => {}
@@ -123,10 +200,7 @@ async fn save(
     image_save_dir: impl AsRef<Path>,
     name: &str,
     image: &Vec<u8>,
-    pixhawk_telemetry: &Option<Telemetry>,
-    offset_position: Option<Point3D>,
-    csb_telemetry: Option<chrono::DateTime<chrono::Local>>,
-    cc_timestamp: Option<chrono::DateTime<chrono::Local>>,
+    metadata: &ImageMetadata,
 ) -> anyhow::Result<PathBuf> {
     let mut image_path = image_save_dir.as_ref().to_owned();
     image_path.push(&name);
@@ -150,16 +224,8 @@ async fn save(
         telem_path.to_string_lossy()
     );
 
-    let cc_timestamp =
-        cc_timestamp.map(|cc_timestamp| cc_timestamp.format(ISO_8601_FORMAT).to_string());
-
-    let telem_bytes = serde_json::to_vec(&serde_json::json!({
-        "pixhawk_telemetry": pixhawk_telemetry,
-        "offset_position": offset_position,
-        "csb_timestamp": csb_telemetry,
-        "cc_timestamp": cc_timestamp,
-    }))
-    .context("failed to serialize telemetry to JSON")?;
+    let telem_bytes =
+        serde_json::to_vec(metadata).context("failed to serialize telemetry to JSON")?;
 
     let mut telem_file = File::create(telem_path)
         .await
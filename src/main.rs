@@ -1,4 +1,8 @@
-use std::{process::exit, sync::Arc, time::Duration};
+use std::{
+    process::exit,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::Context;
 use clap::Parser;
@@ -13,7 +17,7 @@ use tracing::metadata::LevelFilter;
 use tracing_subscriber::{filter::Targets, layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
 use gs::GroundServerClient;
-use pixhawk::{client::PixhawkClient, state::PixhawkEvent};
+use pixhawk::state::PixhawkEvent;
 use state::Telemetry;
 use telemetry::TelemetryStream;
 
@@ -28,10 +32,12 @@ extern crate async_trait;
 
 mod camera;
 mod cli;
+mod geometry;
 mod gimbal;
 mod gs;
 mod image;
 mod pixhawk;
+mod recorder;
 mod scheduler;
 mod server;
 mod state;
@@ -45,6 +51,10 @@ pub struct Channels {
     /// Channel for broadcasting telemetry information gathered from the gimbal and pixhawk
     pixhawk_telemetry: watch::Receiver<Option<Telemetry>>,
 
+    /// Recent telemetry, used to find the telemetry at the time that an image
+    /// was captured.
+    telemetry_history: Arc<Mutex<telemetry::TelemetryHistory>>,
+
     /// Channel for broadcasting updates to the state of the Pixhawk.
     pixhawk_event: broadcast::Sender<PixhawkEvent>,
 
@@ -61,6 +71,9 @@ pub struct Channels {
     /// Channel for sending instructions to the camera.
     camera_cmd: flume::Sender<camera::main::CameraCommand>,
 
+    /// Channel for broadcasting updates to the state of the gimbal.
+    gimbal_event: broadcast::Sender<gimbal::GimbalEvent>,
+
     /// Channel for sending instructions to the gimbal.
     gimbal_cmd: flume::Sender<gimbal::GimbalCommand>,
 
@@ -74,6 +87,13 @@ pub struct Channels {
 
     image_event: broadcast::Sender<image::ImageClientEvent>,
 
+    /// Channel for broadcasting the state of the ground server upload queue.
+    /// This is `None` if the ground server client is not running.
+    ground_server_status: watch::Receiver<Option<gs::GroundServerStatus>>,
+
+    /// Channel for sending instructions to the ground server client.
+    ground_server_cmd: flume::Sender<gs::GroundServerCommand>,
+
     scheduler_cmd: flume::Sender<scheduler::SchedulerCommand>,
 }
 
@@ -83,6 +103,35 @@ impl std::fmt::Debug for Channels {
     }
 }
 
+#[cfg(test)]
+impl Channels {
+    /// Creates channels that are not connected to any other tasks, for tests
+    /// that exercise a single client on its own.
+    fn detached() -> Self {
+        Channels {
+            interrupt: broadcast::channel(1).0,
+            pixhawk_telemetry: watch::channel(None).1,
+            telemetry_history: Arc::new(Mutex::new(telemetry::TelemetryHistory::new())),
+            pixhawk_event: broadcast::channel(1024).0,
+            pixhawk_cmd: flume::unbounded().0,
+            camera_event: broadcast::channel(256).0,
+            #[cfg(feature = "csb")]
+            csb_telemetry: watch::channel(None).1,
+            camera_cmd: flume::unbounded().0,
+            gimbal_event: broadcast::channel(256).0,
+            gimbal_cmd: flume::unbounded().0,
+            #[cfg(feature = "gstreamer")]
+            stream_cmd: flume::unbounded().0,
+            #[cfg(feature = "gstreamer")]
+            save_cmd: flume::unbounded().0,
+            image_event: broadcast::channel(256).0,
+            ground_server_status: watch::channel(None).1,
+            ground_server_cmd: flume::unbounded().0,
+            scheduler_cmd: flume::unbounded().0,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Command<Req, Res, Err = anyhow::Error> {
     request: Req,
@@ -240,10 +289,18 @@ async fn main() -> anyhow::Result<()> {
 
     let config = config.context("failed to read config file")?;
 
-    run_tasks(config).await
+    let replay = main_args
+        .replay
+        .map(|path| recorder::replay::ReplayConfig::new(path, main_args.replay_speed))
+        .transpose()?;
+
+    run_tasks(config, replay).await
 }
 
-async fn run_tasks(config: cli::config::PlaneSystemConfig) -> anyhow::Result<()> {
+async fn run_tasks(
+    config: cli::config::PlaneSystemConfig,
+    replay: Option<recorder::replay::ReplayConfig>,
+) -> anyhow::Result<()> {
     let (interrupt_sender, _) = broadcast::channel(1);
 
     ctrlc::set_handler({
@@ -259,55 +316,91 @@ async fn run_tasks(config: cli::config::PlaneSystemConfig) -> anyhow::Result<()>
 
     {
         let (pixhawk_telemetry_sender, pixhawk_telemetry_receiver) = watch::channel(None);
-        let (pixhawk_event_sender, _) = broadcast::channel(64);
+        let (pixhawk_event_sender, _) = broadcast::channel(1024);
         let (camera_event_sender, _) = broadcast::channel(256);
         #[cfg(feature = "csb")]
         let (csb_telemetry_sender, csb_telemetry_receiver) = watch::channel(None);
         let (camera_cmd_sender, camera_cmd_receiver) = flume::unbounded();
-        let (gimbal_cmd_sender, _gimbal_cmd_receiver) = flume::unbounded();
+        let (gimbal_event_sender, _) = broadcast::channel(256);
+        let (gimbal_cmd_sender, gimbal_cmd_receiver) = flume::unbounded();
         let (scheduler_cmd_sender, scheduler_cmd_receiver) = flume::unbounded();
         #[cfg(feature = "gstreamer")]
         let (stream_cmd_sender, stream_cmd_receiver) = flume::unbounded();
         #[cfg(feature = "gstreamer")]
         let (save_cmd_sender, save_cmd_receiver) = flume::unbounded();
         let (image_event_sender, _) = broadcast::channel(256);
+        let (ground_server_status_sender, ground_server_status_receiver) = watch::channel(None);
+        let (ground_server_cmd_sender, ground_server_cmd_receiver) = flume::unbounded();
         let (pixhawk_cmd_sender, pixhawk_cmd_receiver) = flume::unbounded();
 
         let channels = Arc::new(Channels {
             interrupt: interrupt_sender.clone(),
             pixhawk_telemetry: pixhawk_telemetry_receiver,
+            telemetry_history: Arc::new(Mutex::new(telemetry::TelemetryHistory::new())),
             pixhawk_event: pixhawk_event_sender,
             pixhawk_cmd: pixhawk_cmd_sender,
             camera_event: camera_event_sender,
             #[cfg(feature = "csb")]
             csb_telemetry: csb_telemetry_receiver,
             camera_cmd: camera_cmd_sender,
+            gimbal_event: gimbal_event_sender,
             gimbal_cmd: gimbal_cmd_sender,
             #[cfg(feature = "gstreamer")]
             stream_cmd: stream_cmd_sender,
             #[cfg(feature = "gstreamer")]
             save_cmd: save_cmd_sender,
             image_event: image_event_sender,
+            ground_server_status: ground_server_status_receiver,
+            ground_server_cmd: ground_server_cmd_sender,
             scheduler_cmd: scheduler_cmd_sender,
         });
 
-        if let Some(pixhawk_config) = config.pixhawk {
-            tasks.add("pixhawk", {
-                let channels = channels.clone();
-                async move {
-                    let pixhawk_client = PixhawkClient::connect(
-                        channels.clone(),
-                        pixhawk_cmd_receiver,
-                        pixhawk_config.address,
+        let replaying = replay.is_some();
+
+        let telemetry_source = if let Some(replay_config) = replay {
+            // the recorded events stand in for the pixhawk, so commands sent
+            // to it will fail
+            drop(pixhawk_cmd_receiver);
+
+            if config.pixhawk.is_some() {
+                info!("replaying a flight log, not connecting to the pixhawk");
+            }
+
+            tasks.add(
+                "replay",
+                recorder::replay::run(channels.clone(), replay_config),
+            );
+
+            true
+        } else if let Some(pixhawk_config) = config.pixhawk {
+            if let pixhawk::PixhawkKind::Simulated(sim_config) = pixhawk_config.kind.clone() {
+                tasks.add("pixhawk simulator", {
+                    let sim = pixhawk::sim::SimulatedPixhawk::new(
+                        &pixhawk_config.address,
                         pixhawk_config.mavlink,
+                        sim_config,
                     )
                     .await?;
-                    pixhawk_client.run().await
-                }
+                    sim.run(channels.clone())
+                });
+            }
+
+            tasks.add("pixhawk", {
+                pixhawk::run(channels.clone(), pixhawk_cmd_receiver, pixhawk_config)
             });
 
+            true
+        } else {
+            false
+        };
+
+        if telemetry_source {
             tasks.add("telemetry", {
-                let telemetry = TelemetryStream::new(channels.clone(), pixhawk_telemetry_sender);
+                let telemetry = TelemetryStream::new(
+                    channels.clone(),
+                    pixhawk_telemetry_sender,
+                    config.telemetry,
+                );
                 async move { telemetry.run().await }
             });
         } else {
@@ -335,13 +428,48 @@ async fn run_tasks(config: cli::config::PlaneSystemConfig) -> anyhow::Result<()>
             });
         }
 
-        if let Some(_gimbal_config) = config.gimbal {
-            panic!("gimbal not implemented");
+        if replaying && config.gimbal.is_some() {
+            // the recorded gimbal events would be mixed up with the live ones
+            info!("replaying a flight log, not connecting to the gimbal");
+        } else if let Some(gimbal_config) = config.gimbal {
+            tasks.add("gimbal", {
+                let mut gimbal_client = match (gimbal_config.kind, gimbal_config.device_path) {
+                    (gimbal::GimbalKind::Hardware { .. }, Some(device_path)) => {
+                        gimbal::GimbalClient::connect_with_path(
+                            channels.clone(),
+                            gimbal_cmd_receiver,
+                            device_path,
+                        )?
+                    }
+                    (kind, _) => {
+                        gimbal::GimbalClient::connect(channels.clone(), gimbal_cmd_receiver, kind)?
+                    }
+                };
+
+                async move { gimbal_client.run().await }
+            });
+        }
+
+        if let Some(recorder_config) = config.recorder {
+            tasks.add("flight data recorder", {
+                recorder::run(channels.clone(), recorder_config)
+            });
         }
 
         if let Some(gs_config) = config.ground_server {
+            if let Some(link_config) = gs_config.link.clone() {
+                tasks.add("ground link", {
+                    gs::link::run(channels.clone(), gs_config.address.clone(), link_config)
+                });
+            }
+
             tasks.add("ground server", {
-                let gs_client = GroundServerClient::new(channels.clone(), gs_config.address)?;
+                let gs_client = GroundServerClient::new(
+                    channels.clone(),
+                    ground_server_cmd_receiver,
+                    gs_config,
+                    ground_server_status_sender,
+                )?;
                 async move { gs_client.run().await }
             });
         }
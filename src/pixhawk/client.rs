@@ -1,4 +1,5 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
     sync::{
         atomic::{AtomicU8, Ordering},
         Arc,
@@ -7,60 +8,149 @@ use std::{
 };
 
 use anyhow::Context;
-use bytes::{Buf, BytesMut};
+use bytes::BytesMut;
+use tokio_util::codec::Decoder;
 
-use tokio::net::ToSocketAddrs;
-
-use mavlink::{
-    ardupilotmega as apm, common, error::MessageReadError, error::ParserError, MavHeader,
-    MavlinkVersion,
-};
+use mavlink::{ardupilotmega as apm, common, MavHeader, MavlinkVersion};
 
 use crate::{
-    state::{Attitude, Point3D},
+    cli::config::{PixhawkConfig, PixhawkMessageIntervalConfig, PixhawkParamConfig},
+    state::{Attitude, Battery, GpsFix, GpsStatus, Point3D, Quaternion, Speed},
     util::run_loop,
     Channels,
 };
 
-use super::{state::PixhawkEvent, PixhawkCommand};
+use num_traits::FromPrimitive;
+
+use super::{
+    codec::{LinkStats, MavlinkCodec},
+    mission::MissionItem,
+    params::{decode_param_id, encode_param_id, write_param_file},
+    state::PixhawkEvent,
+    transport::MavlinkTransport,
+    ParamKind, ParamList, ParamValue, PixhawkCommand, PixhawkRequest, PixhawkResponse,
+};
+
+/// The system ID that we use when sending messages. This is the same as the
+/// autopilot's, because we are a component of the same vehicle.
+const SYSTEM_ID: u8 = 1;
+
+/// The component ID that we use when sending messages
+/// (MAV_COMP_ID_ONBOARD_COMPUTER).
+const COMPONENT_ID: u8 = 191;
+
+/// How often we send our heartbeat to the Pixhawk.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long we can go without a heartbeat from the Pixhawk before we consider
+/// the link to be lost.
+const LINK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to wait for each step of a mission transfer before retrying it.
+const MISSION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How many times to retry a step of a mission transfer before giving up.
+const MISSION_RETRIES: usize = 5;
+
+/// How long to wait for the next parameter while downloading the parameter
+/// list before assuming that the rest were lost.
+const PARAM_LIST_IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many times to request a lost parameter before giving up.
+const PARAM_RETRIES: usize = 3;
+
+/// How long to wait before reconnecting after the connection to the Pixhawk
+/// fails for the first time. This doubles after every consecutive failure.
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+
+/// The longest that we will wait before reconnecting to the Pixhawk.
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Connects to the Pixhawk and runs the client. If the connection fails or the
+/// client exits with an error, then telemetry is marked as stale and the
+/// connection is retried with exponential backoff, so that a problem with the
+/// Pixhawk does not bring down the rest of the system.
+pub async fn run(
+    channels: Arc<Channels>,
+    cmd: flume::Receiver<PixhawkCommand>,
+    config: PixhawkConfig,
+) -> anyhow::Result<()> {
+    let mut interrupt_recv = channels.interrupt.subscribe();
+    let mut backoff = RECONNECT_BACKOFF_MIN;
+
+    loop {
+        let started = Instant::now();
+
+        let session = async {
+            let client =
+                PixhawkClient::connect(channels.clone(), cmd.clone(), config.clone()).await?;
+            client.run().await
+        };
+
+        let result = tokio::select! {
+            result = session => result,
+            _ = interrupt_recv.recv() => return Ok(()),
+        };
+
+        let err = match result {
+            // the client only exits successfully when it is interrupted
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        // if the last session was healthy for a while, this is a new problem
+        // and we should try again quickly
+        if started.elapsed() > RECONNECT_BACKOFF_MAX {
+            backoff = RECONNECT_BACKOFF_MIN;
+        }
+
+        error!(
+            "pixhawk connection failed, reconnecting in {:?}: {:?}",
+            backoff, err
+        );
+
+        let _ = channels.pixhawk_event.send(PixhawkEvent::LinkLost);
+
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {},
+            _ = interrupt_recv.recv() => return Ok(()),
+        };
+
+        backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+    }
+}
 
 pub struct PixhawkClient {
-    sock: tokio::net::UdpSocket,
-    seq_num: Option<u8>,
+    transport: Box<dyn MavlinkTransport>,
+    codec: MavlinkCodec,
     buf: BytesMut,
     sequence: AtomicU8,
     channels: Arc<Channels>,
     cmd: flume::Receiver<PixhawkCommand>,
     version: MavlinkVersion,
+    init_params: BTreeMap<String, PixhawkParamConfig>,
+    init_message_intervals: Vec<PixhawkMessageIntervalConfig>,
+
+    next_heartbeat: tokio::time::Instant,
+    last_heartbeat: Option<Instant>,
+    link_up: bool,
 }
 
 impl PixhawkClient {
-    pub async fn connect<A: ToSocketAddrs + Clone>(
+    pub async fn connect(
         channels: Arc<Channels>,
         cmd: flume::Receiver<PixhawkCommand>,
-        addr: A,
-        version: MavlinkVersion,
+        config: PixhawkConfig,
     ) -> anyhow::Result<Self> {
-        let sock = tokio::net::UdpSocket::bind(addr)
-            .await
-            .context("failed to connect to pixhawk")?;
-
-        debug!("waiting for packet from mavproxy");
+        let version = config.mavlink;
 
-        let (_, remote_addr) =
-            tokio::time::timeout(Duration::from_secs(60), sock.recv_from(&mut []))
-                .await
-                .context("timed out while waiting for packet from mavproxy")?
-                .context("error retrieving packet from mavproxy")?;
-
-        info!(
-            "received packet from {:?}, locking to this address",
-            remote_addr
-        );
+        info!("connecting to pixhawk at {}", config.address);
 
-        sock.connect(remote_addr)
+        let transport = config
+            .address
+            .connect()
             .await
-            .context("failed to lock to address")?;
+            .context("failed to connect to pixhawk")?;
 
         match version {
             MavlinkVersion::V1 => debug!("using mavlink v1"),
@@ -68,13 +158,18 @@ impl PixhawkClient {
         };
 
         Ok(PixhawkClient {
-            sock,
-            seq_num: None,
+            transport,
+            codec: MavlinkCodec::new(),
             buf: BytesMut::with_capacity(1024),
             sequence: AtomicU8::default(),
             channels,
             cmd,
             version,
+            init_params: config.params,
+            init_message_intervals: config.message_intervals,
+            next_heartbeat: tokio::time::Instant::now(),
+            last_heartbeat: None,
+            link_up: false,
         })
     }
 
@@ -93,24 +188,98 @@ impl PixhawkClient {
         info!("received heartbeat");
         info!("setting parameters");
 
-        self.set_param_f32("CAM_DURATION", 10.0).await?;
-        self.set_param_u8("CAM_FEEDBACK_PIN", 54).await?;
-        self.set_param_u8("CAM_FEEDBACK_POL", 1).await?;
+        // first apply everything, and then read it back to see what the
+        // pixhawk actually did with it
+        let mut diff = Vec::new();
+
+        for (id, param) in self.init_params.clone() {
+            let id = id.to_uppercase();
+
+            if let Err(err) = self.set_param_kind(&id, param.value, param.kind).await {
+                diff.push(format!(
+                    "{}: wanted {}, refused ({:#})",
+                    id, param.value, err
+                ));
+            }
+        }
+
         self.send_command(
             common::MavCmd::MAV_CMD_DO_DIGICAM_CONTROL,
             [0., 0., 0., 0., 1., 0., 0.],
         )
         .await?;
-        self.send_command(
-            common::MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL,
-            [33., 1000., 0., 0., 0., 0., 0.],
-        )
-        .await?;
-        self.send_command(
-            common::MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL,
-            [30., 1000., 0., 0., 0., 0., 0.],
-        )
-        .await?;
+
+        for interval in self.init_message_intervals.clone() {
+            if let Err(err) = self
+                .send_command(
+                    common::MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL,
+                    [
+                        interval.message_id as f32,
+                        interval.interval_us,
+                        0.,
+                        0.,
+                        0.,
+                        0.,
+                        0.,
+                    ],
+                )
+                .await
+            {
+                diff.push(format!(
+                    "message {} interval: wanted {} us, refused ({:#})",
+                    interval.message_id, interval.interval_us, err
+                ));
+            }
+        }
+
+        info!("checking parameters");
+
+        for (id, param) in self.init_params.clone() {
+            let id = id.to_uppercase();
+
+            match self.get_param(&id).await {
+                Ok((value, _)) => {
+                    if !param.kind.matches(param.value, value as f64) {
+                        diff.push(format!("{}: wanted {}, got {}", id, param.value, value));
+                    }
+                }
+                Err(err) => {
+                    diff.push(format!(
+                        "{}: wanted {}, could not read back ({:#})",
+                        id, param.value, err
+                    ));
+                }
+            }
+        }
+
+        for interval in self.init_message_intervals.clone() {
+            match self.get_message_interval(interval.message_id).await {
+                Ok(interval_us) => {
+                    if interval_us as f32 != interval.interval_us {
+                        diff.push(format!(
+                            "message {} interval: wanted {} us, got {} us",
+                            interval.message_id, interval.interval_us, interval_us
+                        ));
+                    }
+                }
+                Err(err) => {
+                    diff.push(format!(
+                        "message {} interval: wanted {} us, could not read back ({:#})",
+                        interval.message_id, interval.interval_us, err
+                    ));
+                }
+            }
+        }
+
+        if diff.is_empty() {
+            info!("pixhawk accepted all parameters");
+        } else {
+            warn!(
+                "pixhawk refused or changed {} parameter(s):\n  {}",
+                diff.len(),
+                diff.join("\n  ")
+            );
+        }
 
         info!("finished initialization");
 
@@ -119,20 +288,25 @@ impl PixhawkClient {
 
     /// Sends a message to the Pixhawk.
     pub async fn send(&mut self, message: apm::MavMessage) -> anyhow::Result<()> {
-        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
-
         debug!("sending message: {:?}", &message);
 
+        self.write(message).await
+    }
+
+    /// Sends a message to the Pixhawk without logging it.
+    async fn write(&mut self, message: apm::MavMessage) -> anyhow::Result<()> {
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+
         let header = MavHeader {
             sequence,
-            system_id: 1,
-            component_id: 1,
+            system_id: SYSTEM_ID,
+            component_id: COMPONENT_ID,
         };
 
         let mut buf = Vec::with_capacity(1024);
 
         mavlink::write_versioned_msg(&mut buf, self.version, header, &message)?;
-        self.sock.send(buf.as_ref()).await?;
+        self.transport.send(buf.as_ref()).await?;
 
         Ok(())
     }
@@ -140,115 +314,75 @@ impl PixhawkClient {
     /// Waits for a message from the Pixhawk, reacts to it, and returns it.
     pub async fn recv(&mut self) -> anyhow::Result<apm::MavMessage> {
         loop {
-            let mut chunk = vec![0; 1024];
-
-            let magic = match self.version {
-                MavlinkVersion::V1 => 0xFE,
-                MavlinkVersion::V2 => 0xFD,
-            };
-
-            trace!("buf is {:?} bytes long", self.buf.len());
+            // the codec consumes frames from the buffer synchronously, so if
+            // this future is cancelled while waiting for more bytes, nothing
+            // is lost
+            if let Some((header, msg)) = self.codec.decode(&mut self.buf)? {
+                trace!("received message from {:?}: {:?}", header, msg);
 
-            let magic_position = loop {
-                let magic_position = self.buf.iter().position(|&b| b == magic);
+                self.handle(&msg).await?;
 
-                match magic_position {
-                    // we need at least two bytes after the magic in the buffer
-                    Some(magic_position) if magic_position + 2 < self.buf.len() => {
-                        break magic_position
-                    }
-                    res => {
-                        trace!("requesting more bytes, magic too close to end ({:?})", res);
-
-                        let (n, addr) = self.sock.recv_from(&mut chunk[..]).await?;
-                        self.buf.extend(&chunk[..n]);
-                        trace!("read {:?} bytes from {:?}", n, addr);
-                    }
-                };
-            };
+                return Ok(msg);
+            }
 
             trace!(
-                "found magic at position {:?} in buf length {:?}",
-                magic_position,
+                "requesting more bytes, buf is {:?} bytes long",
                 self.buf.len()
             );
 
-            let payload_len = self.buf[magic_position + 1];
+            let mut chunk = [0; 1024];
 
-            let seq_num = self.buf[magic_position + 4];
-
-            if let Some(prev_seq_num) = &mut self.seq_num {
-                let expected_seq_num = prev_seq_num.wrapping_add(1);
+            // wake up to send our heartbeat even if the pixhawk is silent
+            let n = tokio::select! {
+                n = self.transport.recv(&mut chunk[..]) => Some(n?),
+                _ = tokio::time::sleep_until(self.next_heartbeat) => None,
+            };
 
-                if expected_seq_num != seq_num {
-                    debug!("unexpected sequence number {seq_num} (wanted {expected_seq_num}) for pixhawk packet, assuming packet loss");
-                    let skip = magic_position + 1;
-                    trace!("skipping forward {skip} bytes");
-                    self.buf.advance(skip);
-                    continue;
-                } else {
-                    *prev_seq_num = seq_num;
+            match n {
+                Some(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    trace!("read {:?} bytes", n);
                 }
-            } else {
-                self.seq_num = Some(seq_num);
+                None => self.heartbeat().await?,
             }
+        }
+    }
 
-            trace!("seq num = {seq_num}");
+    /// Sends our heartbeat to the Pixhawk and checks whether we are still
+    /// receiving the Pixhawk's heartbeat.
+    async fn heartbeat(&mut self) -> anyhow::Result<()> {
+        self.next_heartbeat = tokio::time::Instant::now() + HEARTBEAT_INTERVAL;
 
-            let msg_body_size = match self.version {
-                // in v1: 1 byte magic + 1 byte payload len + 4 byte header + 2 byte checksum
-                MavlinkVersion::V1 => payload_len as usize + 8,
-                // in v2: 1 byte magic + 1 byte payload len + 8 byte header + 2 byte checksum
-                MavlinkVersion::V2 => payload_len as usize + 12,
-            };
+        let message =
+            apm::MavMessage::common(common::MavMessage::HEARTBEAT(common::HEARTBEAT_DATA {
+                custom_mode: 0,
+                mavtype: common::MavType::MAV_TYPE_ONBOARD_CONTROLLER,
+                autopilot: common::MavAutopilot::MAV_AUTOPILOT_INVALID,
+                base_mode: common::MavModeFlag::empty(),
+                system_status: common::MavState::MAV_STATE_ACTIVE,
+                mavlink_version: 3,
+            }));
 
-            trace!("need {:?} bytes", msg_body_size);
+        self.write(message).await?;
 
-            while magic_position + msg_body_size > self.buf.len() {
-                trace!("requesting more bytes, buffer insufficient");
+        if let Some(last_heartbeat) = self.last_heartbeat {
+            if self.link_up && last_heartbeat.elapsed() > LINK_TIMEOUT {
+                warn!(
+                    "no heartbeat from pixhawk in {:?}, link lost",
+                    last_heartbeat.elapsed()
+                );
 
-                let mut chunk = vec![0; 1024];
-                let (n, addr) = self.sock.recv_from(&mut chunk[..]).await?;
-                self.buf.extend(&chunk[..n]);
-                trace!("read {:?} bytes from {:?}", n, addr);
+                self.link_up = false;
+                let _ = self.channels.pixhawk_event.send(PixhawkEvent::LinkLost);
             }
+        }
 
-            let msg_content = &self.buf[magic_position..magic_position + msg_body_size];
-
-            // if we get a bad checksum, just drop the message and try again
-            let msg = match mavlink::read_versioned_msg(&mut &msg_content[..], self.version) {
-                Ok((_, msg)) => {
-                    let skip = magic_position + msg_body_size;
-                    trace!("parsed message, success, skipping {:?} bytes", skip);
-                    self.buf.advance(skip);
-                    msg
-                }
-                Err(MessageReadError::Parse(ParserError::InvalidChecksum { .. })) => {
-                    debug!(
-                        "message parsing failure (invalid checksum); buffer contents: {:02x?}",
-                        msg_content,
-                    );
-                    trace!("got invalid checksum, dropping message");
-                    let skip = magic_position + msg_body_size;
-                    // let skip = magic_position + 1;
-                    self.buf.advance(skip);
-                    continue;
-                }
-                Err(err) => {
-                    warn!(
-                        "message parsing failure ({:?}); buffer contents: {:02x?}",
-                        err, msg_content
-                    );
-                    return Err(err).context("error while parsing message");
-                }
-            };
-
-            trace!("received message: {:?}", msg);
-
-            self.handle(&msg).await?;
+        Ok(())
+    }
 
-            return Ok(msg);
-        }
+    /// Returns packet statistics for the link to the Pixhawk.
+    pub fn link_stats(&self) -> &LinkStats {
+        self.codec.stats()
     }
 
     pub async fn run(mut self) -> anyhow::Result<()> {
@@ -275,13 +409,119 @@ impl PixhawkClient {
         Ok(())
     }
 
-    async fn exec(&mut self, _cmd: PixhawkCommand) -> anyhow::Result<()> {
-        unimplemented!()
+    async fn exec(&mut self, cmd: PixhawkCommand) -> anyhow::Result<()> {
+        let result = match cmd.request().clone() {
+            PixhawkRequest::SetParam { id, value, kind } => self
+                .set_param_kind(&id, value, kind)
+                .await
+                .map(|value| PixhawkResponse::Param { id, value }),
+            PixhawkRequest::GetParam { id } => {
+                self.get_param(&id)
+                    .await
+                    .map(|(value, _)| PixhawkResponse::Param {
+                        id,
+                        value: value as f64,
+                    })
+            }
+            PixhawkRequest::DownloadMission => self
+                .download_mission()
+                .await
+                .map(|items| PixhawkResponse::Mission { items }),
+            PixhawkRequest::UploadMission { items } => self
+                .upload_mission(items)
+                .await
+                .map(|_| PixhawkResponse::Unit),
+            PixhawkRequest::LinkStats => Ok(PixhawkResponse::LinkStats(self.link_stats().clone())),
+            PixhawkRequest::DumpParams { path } => match self.fetch_all_params().await {
+                Ok(params) => {
+                    write_param_file(&path, &params)
+                        .await
+                        .map(|_| PixhawkResponse::ParamDump {
+                            path,
+                            count: params.len(),
+                        })
+                }
+                Err(err) => Err(err),
+            },
+            PixhawkRequest::Command { command, params } => {
+                match common::MavCmd::from_u16(command) {
+                    Some(command) => {
+                        if params.len() > 7 {
+                            Err(anyhow!("commands take at most 7 parameters"))
+                        } else {
+                            let mut all_params = [0.; 7];
+                            all_params[..params.len()].copy_from_slice(&params[..]);
+
+                            self.send_command(command, all_params).await.map(|result| {
+                                PixhawkResponse::CommandResult {
+                                    result: format!("{:?}", result),
+                                }
+                            })
+                        }
+                    }
+                    None => Err(anyhow!("unknown command id {}", command)),
+                }
+            }
+            PixhawkRequest::SetMessageInterval {
+                message_id,
+                interval,
+            } => self
+                .send_command(
+                    common::MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL,
+                    [message_id as f32, interval, 0., 0., 0., 0., 0.],
+                )
+                .await
+                .map(|result| PixhawkResponse::CommandResult {
+                    result: format!("{:?}", result),
+                }),
+            PixhawkRequest::TriggerCamera => self
+                .send_command(
+                    common::MavCmd::MAV_CMD_DO_DIGICAM_CONTROL,
+                    [0., 0., 0., 0., 1., 0., 0.],
+                )
+                .await
+                .map(|_| PixhawkResponse::Unit),
+        };
+
+        if let Err(err) = &result {
+            warn!("pixhawk command failed: {:?}", err);
+        }
+
+        let _ = cmd.respond(result);
+
+        Ok(())
     }
 
     /// Reacts to a message received from the Pixhawk.
-    async fn handle(&self, message: &apm::MavMessage) -> anyhow::Result<()> {
+    async fn handle(&mut self, message: &apm::MavMessage) -> anyhow::Result<()> {
         match message {
+            // ground stations also send heartbeats, so only pay attention to
+            // the ones that come from an autopilot
+            apm::MavMessage::common(common::MavMessage::HEARTBEAT(data))
+                if data.autopilot != common::MavAutopilot::MAV_AUTOPILOT_INVALID =>
+            {
+                if let Some(last_heartbeat) = self.last_heartbeat {
+                    if !self.link_up {
+                        info!(
+                            "received heartbeat from pixhawk after {:?}, link restored",
+                            last_heartbeat.elapsed()
+                        );
+
+                        let _ = self.channels.pixhawk_event.send(PixhawkEvent::LinkRestored);
+                    }
+                }
+
+                self.last_heartbeat = Some(Instant::now());
+                self.link_up = true;
+
+                let _ = self.channels.pixhawk_event.send(PixhawkEvent::Heartbeat {
+                    mode: flight_mode(data.mavtype, data.custom_mode),
+                    armed: data
+                        .base_mode
+                        .contains(common::MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED),
+                    system_status: data.system_status,
+                });
+            }
             apm::MavMessage::common(common::MavMessage::GLOBAL_POSITION_INT(data)) => {
                 let _ = self.channels.pixhawk_event.send(PixhawkEvent::Gps {
                     position: Point3D {
@@ -307,6 +547,102 @@ impl PixhawkClient {
                     ),
                 });
             }
+            apm::MavMessage::common(common::MavMessage::ATTITUDE_QUATERNION(data)) => {
+                let _ = self
+                    .channels
+                    .pixhawk_event
+                    .send(PixhawkEvent::OrientationQuaternion {
+                        attitude: Quaternion {
+                            w: data.q1,
+                            x: data.q2,
+                            y: data.q3,
+                            z: data.q4,
+                        },
+                    });
+            }
+            apm::MavMessage::common(common::MavMessage::GPS_RAW_INT(data)) => {
+                let fix = match data.fix_type {
+                    common::GpsFixType::GPS_FIX_TYPE_NO_GPS => GpsFix::NoGps,
+                    common::GpsFixType::GPS_FIX_TYPE_NO_FIX => GpsFix::NoFix,
+                    common::GpsFixType::GPS_FIX_TYPE_2D_FIX => GpsFix::Fix2D,
+                    common::GpsFixType::GPS_FIX_TYPE_3D_FIX => GpsFix::Fix3D,
+                    common::GpsFixType::GPS_FIX_TYPE_DGPS => GpsFix::Dgps,
+                    common::GpsFixType::GPS_FIX_TYPE_RTK_FLOAT => GpsFix::RtkFloat,
+                    common::GpsFixType::GPS_FIX_TYPE_RTK_FIXED => GpsFix::RtkFixed,
+                    common::GpsFixType::GPS_FIX_TYPE_STATIC => GpsFix::Static,
+                    common::GpsFixType::GPS_FIX_TYPE_PPP => GpsFix::Ppp,
+                };
+
+                let _ = self
+                    .channels
+                    .pixhawk_event
+                    .send(PixhawkEvent::GpsStatus(GpsStatus {
+                        fix,
+                        // unknown values are sent as the maximum value
+                        satellites: Some(data.satellites_visible)
+                            .filter(|&satellites| satellites != u8::MAX),
+                        hdop: Some(data.eph)
+                            .filter(|&eph| eph != u16::MAX)
+                            .map(|eph| eph as f32 / 100.),
+                    }));
+            }
+            apm::MavMessage::common(common::MavMessage::VFR_HUD(data)) => {
+                let _ = self.channels.pixhawk_event.send(PixhawkEvent::Speed(Speed {
+                    airspeed: data.airspeed,
+                    groundspeed: data.groundspeed,
+                    climb_rate: data.climb,
+                }));
+            }
+            apm::MavMessage::common(common::MavMessage::SYS_STATUS(data)) => {
+                let _ = self
+                    .channels
+                    .pixhawk_event
+                    .send(PixhawkEvent::Battery(Battery {
+                        // voltage is in millivolts, unknown is u16::MAX
+                        voltage: Some(data.voltage_battery)
+                            .filter(|&voltage| voltage != u16::MAX)
+                            .map(|voltage| voltage as f32 / 1e3),
+                        // current is in centiamps, unknown is -1
+                        current: Some(data.current_battery)
+                            .filter(|&current| current != -1)
+                            .map(|current| current as f32 / 1e2),
+                        // remaining is in percent, unknown is -1
+                        remaining: Some(data.battery_remaining)
+                            .filter(|&remaining| remaining >= 0)
+                            .map(|remaining| remaining as u8),
+                    }));
+            }
+            apm::MavMessage::common(common::MavMessage::MISSION_CURRENT(data)) => {
+                let _ = self
+                    .channels
+                    .pixhawk_event
+                    .send(PixhawkEvent::MissionCurrent { seq: data.seq });
+            }
+            apm::MavMessage::common(common::MavMessage::STATUSTEXT(data)) => {
+                let text = data
+                    .text
+                    .iter()
+                    .take_while(|&&c| c != '\0')
+                    .collect::<String>();
+
+                match data.severity {
+                    common::MavSeverity::MAV_SEVERITY_EMERGENCY
+                    | common::MavSeverity::MAV_SEVERITY_ALERT
+                    | common::MavSeverity::MAV_SEVERITY_CRITICAL
+                    | common::MavSeverity::MAV_SEVERITY_ERROR => {
+                        error!("pixhawk: {}", text)
+                    }
+                    common::MavSeverity::MAV_SEVERITY_WARNING => warn!("pixhawk: {}", text),
+                    common::MavSeverity::MAV_SEVERITY_NOTICE
+                    | common::MavSeverity::MAV_SEVERITY_INFO => info!("pixhawk: {}", text),
+                    common::MavSeverity::MAV_SEVERITY_DEBUG => debug!("pixhawk: {}", text),
+                }
+
+                let _ = self.channels.pixhawk_event.send(PixhawkEvent::StatusText {
+                    severity: data.severity,
+                    text,
+                });
+            }
             apm::MavMessage::CAMERA_FEEDBACK(data) => {
                 let _ = self.channels.pixhawk_event.send(PixhawkEvent::Image {
                     foc_len: data.foc_len,
@@ -390,10 +726,7 @@ impl PixhawkClient {
     ) -> anyhow::Result<T> {
         debug!("setting param {:?} to {:?}", id, param_value);
 
-        let mut param_id: [char; 16] = ['\0'; 16];
-        for (index, character) in id.char_indices() {
-            param_id[index] = character;
-        }
+        let param_id = encode_param_id(id);
 
         let message =
             apm::MavMessage::common(common::MavMessage::PARAM_SET(common::PARAM_SET_DATA {
@@ -425,7 +758,12 @@ impl PixhawkClient {
 
         match ack_message {
             apm::MavMessage::common(common::MavMessage::PARAM_VALUE(data)) => {
-                let param_value = num_traits::cast(data.param_value).unwrap();
+                let param_value = num_traits::cast(data.param_value).with_context(|| {
+                    format!(
+                        "pixhawk reported value {} for param {:?}, which does not fit its type",
+                        data.param_value, id
+                    )
+                })?;
                 debug!("received ack, current param value is {:?}", param_value);
                 Ok(param_value)
             }
@@ -433,7 +771,385 @@ impl PixhawkClient {
         }
     }
 
-    /// Sets a parameter on the Pixhawk and waits for acknowledgement. The
+    /// Reads a parameter from the Pixhawk. Returns the value of the parameter
+    /// and its type. The default timeout is 10 seconds.
+    pub async fn get_param(&mut self, id: &str) -> anyhow::Result<(f32, common::MavParamType)> {
+        debug!("getting param {:?}", id);
+
+        let param_id = encode_param_id(id);
+
+        let message = apm::MavMessage::common(common::MavMessage::PARAM_REQUEST_READ(
+            common::PARAM_REQUEST_READ_DATA {
+                param_id,
+                // -1 means that the parameter is looked up by its id
+                param_index: -1,
+                target_system: 0,
+                target_component: 0,
+            },
+        ));
+
+        self.send(message).await?;
+
+        debug!("sent request, waiting for value");
+
+        let value_message = self
+            .wait_for_message(
+                |message| match message {
+                    apm::MavMessage::common(common::MavMessage::PARAM_VALUE(data)) => {
+                        data.param_id == param_id
+                    }
+                    _ => false,
+                },
+                Duration::from_secs(10),
+            )
+            .await
+            .context("Error occurred while waiting for parameter value")?;
+
+        match value_message {
+            apm::MavMessage::common(common::MavMessage::PARAM_VALUE(data)) => {
+                debug!("received param value {:?}", data.param_value);
+                Ok((data.param_value, data.param_type))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Downloads every parameter from the Pixhawk using `PARAM_REQUEST_LIST`.
+    /// Parameters that are lost in transit are requested again individually
+    /// by index, up to `PARAM_RETRIES` times.
+    pub async fn fetch_all_params(&mut self) -> anyhow::Result<ParamList> {
+        info!("downloading parameter list");
+
+        let message = apm::MavMessage::common(common::MavMessage::PARAM_REQUEST_LIST(
+            common::PARAM_REQUEST_LIST_DATA {
+                target_system: 0,
+                target_component: 0,
+            },
+        ));
+
+        self.send(message).await?;
+
+        let mut params = ParamList::new();
+        let mut received = BTreeSet::new();
+        let mut param_count = None;
+        let mut tries = 0;
+
+        loop {
+            self.receive_params(&mut params, &mut received, &mut param_count)
+                .await?;
+
+            let param_count =
+                param_count.context("did not receive any parameters from the pixhawk")?;
+
+            let missing = (0..param_count)
+                .filter(|index| !received.contains(index))
+                .collect::<Vec<_>>();
+
+            if missing.is_empty() {
+                break;
+            }
+
+            if tries >= PARAM_RETRIES {
+                bail!(
+                    "failed to retrieve {} of {} parameters after {} tries",
+                    missing.len(),
+                    param_count,
+                    tries
+                );
+            }
+
+            tries += 1;
+
+            debug!(
+                "received {} of {} parameters, requesting {} missing parameters",
+                received.len(),
+                param_count,
+                missing.len()
+            );
+
+            for index in missing {
+                let message = apm::MavMessage::common(common::MavMessage::PARAM_REQUEST_READ(
+                    common::PARAM_REQUEST_READ_DATA {
+                        param_id: ['\0'; 16],
+                        param_index: index as i16,
+                        target_system: 0,
+                        target_component: 0,
+                    },
+                ));
+
+                self.send(message).await?;
+            }
+        }
+
+        info!("downloaded {} parameters", params.len());
+
+        Ok(params)
+    }
+
+    /// Collects `PARAM_VALUE` messages until every parameter has been received
+    /// or no parameter has arrived for `PARAM_LIST_IDLE_TIMEOUT`. Other
+    /// messages don't count as activity, because the Pixhawk keeps streaming
+    /// telemetry while it sends the parameters.
+    async fn receive_params(
+        &mut self,
+        params: &mut ParamList,
+        received: &mut BTreeSet<u16>,
+        param_count: &mut Option<u16>,
+    ) -> anyhow::Result<()> {
+        let mut last_param = Instant::now();
+
+        loop {
+            if let Some(param_count) = *param_count {
+                if received.len() >= param_count as usize {
+                    break;
+                }
+            }
+
+            let remaining = PARAM_LIST_IDLE_TIMEOUT.saturating_sub(last_param.elapsed());
+
+            let message = match tokio::time::timeout(remaining, self.recv()).await {
+                Ok(message) => message?,
+                Err(_) => break,
+            };
+
+            if let apm::MavMessage::common(common::MavMessage::PARAM_VALUE(data)) = message {
+                last_param = Instant::now();
+                *param_count = Some(data.param_count);
+                received.insert(data.param_index);
+                params.insert(
+                    decode_param_id(&data.param_id),
+                    ParamValue {
+                        index: data.param_index,
+                        value: data.param_value,
+                        kind: data.param_type,
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends `message` and waits for a reply from which `extract` returns a
+    /// value. The message is resent if there is no reply after
+    /// `MISSION_TIMEOUT`.
+    async fn request_with_retry<T, F: Fn(&apm::MavMessage) -> Option<T>>(
+        &mut self,
+        message: apm::MavMessage,
+        extract: F,
+    ) -> anyhow::Result<T> {
+        let mut tries = 0;
+
+        loop {
+            self.send(message.clone()).await?;
+
+            let result = self
+                .wait_for_message(|message| extract(message).is_some(), MISSION_TIMEOUT)
+                .await;
+
+            match result {
+                Ok(reply) => return Ok(extract(&reply).unwrap()),
+                Err(err) => {
+                    tries += 1;
+
+                    if tries >= MISSION_RETRIES {
+                        return Err(err).context(format!("no reply after {} tries", tries));
+                    }
+
+                    debug!("no reply, retrying ({}/{})", tries, MISSION_RETRIES);
+                }
+            }
+        }
+    }
+
+    /// Downloads the current mission from the Pixhawk.
+    pub async fn download_mission(&mut self) -> anyhow::Result<Vec<MissionItem>> {
+        info!("downloading mission");
+
+        let count = self
+            .request_with_retry(
+                apm::MavMessage::common(common::MavMessage::MISSION_REQUEST_LIST(
+                    common::MISSION_REQUEST_LIST_DATA {
+                        target_system: 0,
+                        target_component: 0,
+                        mission_type: common::MavMissionType::MAV_MISSION_TYPE_MISSION,
+                    },
+                )),
+                |message| match message {
+                    apm::MavMessage::common(common::MavMessage::MISSION_COUNT(data)) => {
+                        Some(data.count)
+                    }
+                    _ => None,
+                },
+            )
+            .await
+            .context("failed to get mission item count")?;
+
+        debug!("mission has {} items", count);
+
+        let mut items = Vec::with_capacity(count as usize);
+
+        for seq in 0..count {
+            let item = self
+                .request_with_retry(
+                    apm::MavMessage::common(common::MavMessage::MISSION_REQUEST_INT(
+                        common::MISSION_REQUEST_INT_DATA {
+                            seq,
+                            target_system: 0,
+                            target_component: 0,
+                            mission_type: common::MavMissionType::MAV_MISSION_TYPE_MISSION,
+                        },
+                    )),
+                    |message| match message {
+                        apm::MavMessage::common(common::MavMessage::MISSION_ITEM_INT(data))
+                            if data.seq == seq =>
+                        {
+                            Some(MissionItem::from_mavlink(data))
+                        }
+                        _ => None,
+                    },
+                )
+                .await
+                .with_context(|| format!("failed to get mission item {}", seq))?;
+
+            items.push(item);
+        }
+
+        self.send(apm::MavMessage::common(common::MavMessage::MISSION_ACK(
+            common::MISSION_ACK_DATA {
+                target_system: 0,
+                target_component: 0,
+                mavtype: common::MavMissionResult::MAV_MISSION_ACCEPTED,
+                mission_type: common::MavMissionType::MAV_MISSION_TYPE_MISSION,
+            },
+        )))
+        .await?;
+
+        info!("downloaded {} mission items", items.len());
+
+        let _ = self.channels.pixhawk_event.send(PixhawkEvent::Mission {
+            items: items.clone(),
+        });
+
+        Ok(items)
+    }
+
+    /// Replaces the mission on the Pixhawk. The Pixhawk drives this exchange
+    /// by requesting each item after we tell it how many there are.
+    pub async fn upload_mission(&mut self, items: Vec<MissionItem>) -> anyhow::Result<()> {
+        info!("uploading {} mission items", items.len());
+
+        let count = items.len() as u16;
+
+        // validate the items before we start so we do not leave the pixhawk
+        // with half of a mission
+        let messages = items
+            .iter()
+            .enumerate()
+            .map(|(seq, item)| item.to_mavlink(seq as u16))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let count_message = apm::MavMessage::common(common::MavMessage::MISSION_COUNT(
+            common::MISSION_COUNT_DATA {
+                count,
+                target_system: 0,
+                target_component: 0,
+                mission_type: common::MavMissionType::MAV_MISSION_TYPE_MISSION,
+            },
+        ));
+
+        self.send(count_message.clone()).await?;
+
+        // the message that we will resend if the pixhawk stops responding
+        let mut last_message = count_message;
+        let mut tries = 0;
+
+        loop {
+            let reply = self
+                .wait_for_message(
+                    |message| match message {
+                        apm::MavMessage::common(common::MavMessage::MISSION_REQUEST_INT(_))
+                        | apm::MavMessage::common(common::MavMessage::MISSION_REQUEST(_))
+                        | apm::MavMessage::common(common::MavMessage::MISSION_ACK(_)) => true,
+                        _ => false,
+                    },
+                    MISSION_TIMEOUT,
+                )
+                .await;
+
+            let reply = match reply {
+                Ok(reply) => reply,
+                Err(err) => {
+                    tries += 1;
+
+                    if tries >= MISSION_RETRIES {
+                        return Err(err)
+                            .context(format!("mission upload stalled after {} tries", tries));
+                    }
+
+                    debug!("no reply, retrying ({}/{})", tries, MISSION_RETRIES);
+                    self.send(last_message.clone()).await?;
+                    continue;
+                }
+            };
+
+            let seq = match reply {
+                apm::MavMessage::common(common::MavMessage::MISSION_REQUEST_INT(data)) => data.seq,
+                apm::MavMessage::common(common::MavMessage::MISSION_REQUEST(data)) => data.seq,
+                apm::MavMessage::common(common::MavMessage::MISSION_ACK(data)) => {
+                    match data.mavtype {
+                        common::MavMissionResult::MAV_MISSION_ACCEPTED => break,
+                        result => bail!("pixhawk rejected mission: {:?}", result),
+                    }
+                }
+                _ => unreachable!(),
+            };
+
+            let item = messages
+                .get(seq as usize)
+                .with_context(|| format!("pixhawk requested nonexistent mission item {}", seq))?;
+
+            debug!("sending mission item {}", seq);
+
+            last_message =
+                apm::MavMessage::common(common::MavMessage::MISSION_ITEM_INT(item.clone()));
+            tries = 0;
+
+            self.send(last_message.clone()).await?;
+        }
+
+        info!("uploaded {} mission items", count);
+
+        let _ = self
+            .channels
+            .pixhawk_event
+            .send(PixhawkEvent::Mission { items });
+
+        Ok(())
+    }
+
+    /// Sets a parameter on the Pixhawk whose type is only known at runtime
+    /// and returns the value that the Pixhawk acknowledged.
+    pub async fn set_param_kind(
+        &mut self,
+        id: &str,
+        value: f64,
+        kind: ParamKind,
+    ) -> anyhow::Result<f64> {
+        Ok(match kind {
+            ParamKind::F32 => self.set_param_f32(id, param_value(value, kind)?).await? as f64,
+            ParamKind::U8 => self.set_param_u8(id, param_value(value, kind)?).await? as f64,
+            ParamKind::I8 => self.set_param_i8(id, param_value(value, kind)?).await? as f64,
+            ParamKind::U16 => self.set_param_u16(id, param_value(value, kind)?).await? as f64,
+            ParamKind::I16 => self.set_param_i16(id, param_value(value, kind)?).await? as f64,
+            ParamKind::U32 => self.set_param_u32(id, param_value(value, kind)?).await? as f64,
+            ParamKind::I32 => self.set_param_i32(id, param_value(value, kind)?).await? as f64,
+            ParamKind::U64 => self.set_param_u64(id, param_value(value, kind)?).await? as f64,
+            ParamKind::I64 => self.set_param_i64(id, param_value(value, kind)?).await? as f64,
+        })
+    }
+
+    /// Sends a command to the Pixhawk and waits for acknowledgement. The
     /// default timeout is 10 seconds.
     pub async fn send_command(
         &mut self,
@@ -442,24 +1158,8 @@ impl PixhawkClient {
     ) -> anyhow::Result<common::MavResult> {
         debug!("sending command {:?} ({:?})", command, params);
 
-        let message = apm::MavMessage::common(common::MavMessage::COMMAND_LONG(
-            common::COMMAND_LONG_DATA {
-                command,
-                confirmation: 0,
-                param1: params[0],
-                param2: params[1],
-                param3: params[2],
-                param4: params[3],
-                param5: params[4],
-                param6: params[5],
-                param7: params[6],
-                target_system: 0,
-                target_component: 0,
-            },
-        ));
-
         // send message
-        self.send(message).await?;
+        self.send(command_long(command, params)).await?;
 
         debug!("sent command, waiting for ack");
 
@@ -492,6 +1192,38 @@ impl PixhawkClient {
         }
     }
 
+    /// Asks the Pixhawk how often it is sending a message. Returns the interval
+    /// in microseconds, where -1 means that the message is disabled.
+    pub async fn get_message_interval(&mut self, message_id: u32) -> anyhow::Result<i32> {
+        debug!("getting interval of message {}", message_id);
+
+        self.send(command_long(
+            common::MavCmd::MAV_CMD_GET_MESSAGE_INTERVAL,
+            [message_id as f32, 0., 0., 0., 0., 0., 0.],
+        ))
+        .await?;
+
+        let interval_message = self
+            .wait_for_message(
+                |message| match message {
+                    apm::MavMessage::common(common::MavMessage::MESSAGE_INTERVAL(data)) => {
+                        data.message_id as u32 == message_id
+                    }
+                    _ => false,
+                },
+                Duration::from_secs(10),
+            )
+            .await
+            .context("Error occurred while waiting for message interval")?;
+
+        match interval_message {
+            apm::MavMessage::common(common::MavMessage::MESSAGE_INTERVAL(data)) => {
+                Ok(data.interval_us)
+            }
+            _ => unreachable!(),
+        }
+    }
+
     pub async fn set_param_f32(&mut self, id: &str, value: f32) -> anyhow::Result<f32> {
         self.set_param(id, value, common::MavParamType::MAV_PARAM_TYPE_REAL32)
             .await
@@ -537,3 +1269,384 @@ impl PixhawkClient {
             .await
     }
 }
+
+/// Converts a parameter value that was entered by the user to the type of the
+/// parameter. Values that are out of range, or that are not whole numbers for
+/// integer parameters, are rejected instead of being truncated.
+fn param_value<T: num_traits::NumCast>(value: f64, kind: ParamKind) -> anyhow::Result<T> {
+    if kind != ParamKind::F32 && value.fract() != 0. {
+        bail!(
+            "{} is not a whole number, so it can't be set on a {:?} param",
+            value,
+            kind
+        );
+    }
+
+    match num_traits::cast(value) {
+        Some(value) => Ok(value),
+        None => bail!("{} is out of range for a {:?} param", value, kind),
+    }
+}
+
+fn command_long(command: common::MavCmd, params: [f32; 7]) -> apm::MavMessage {
+    apm::MavMessage::common(common::MavMessage::COMMAND_LONG(
+        common::COMMAND_LONG_DATA {
+            command,
+            confirmation: 0,
+            param1: params[0],
+            param2: params[1],
+            param3: params[2],
+            param4: params[3],
+            param5: params[4],
+            param6: params[5],
+            param7: params[6],
+            target_system: 0,
+            target_component: 0,
+        },
+    ))
+}
+
+/// Returns the name of the flight mode that ArduPilot reports in the
+/// `custom_mode` field of its heartbeat. The meaning of this field depends on
+/// the type of vehicle.
+fn flight_mode(mavtype: common::MavType, custom_mode: u32) -> String {
+    let name = match mavtype {
+        common::MavType::MAV_TYPE_FIXED_WING
+        | common::MavType::MAV_TYPE_VTOL_DUOROTOR
+        | common::MavType::MAV_TYPE_VTOL_QUADROTOR
+        | common::MavType::MAV_TYPE_VTOL_TILTROTOR => match custom_mode {
+            0 => Some("MANUAL"),
+            1 => Some("CIRCLE"),
+            2 => Some("STABILIZE"),
+            3 => Some("TRAINING"),
+            4 => Some("ACRO"),
+            5 => Some("FBWA"),
+            6 => Some("FBWB"),
+            7 => Some("CRUISE"),
+            8 => Some("AUTOTUNE"),
+            10 => Some("AUTO"),
+            11 => Some("RTL"),
+            12 => Some("LOITER"),
+            13 => Some("TAKEOFF"),
+            14 => Some("AVOID_ADSB"),
+            15 => Some("GUIDED"),
+            17 => Some("QSTABILIZE"),
+            18 => Some("QHOVER"),
+            19 => Some("QLOITER"),
+            20 => Some("QLAND"),
+            21 => Some("QRTL"),
+            22 => Some("QAUTOTUNE"),
+            23 => Some("QACRO"),
+            24 => Some("THERMAL"),
+            _ => None,
+        },
+        common::MavType::MAV_TYPE_QUADROTOR
+        | common::MavType::MAV_TYPE_HEXAROTOR
+        | common::MavType::MAV_TYPE_OCTOROTOR
+        | common::MavType::MAV_TYPE_TRICOPTER
+        | common::MavType::MAV_TYPE_COAXIAL
+        | common::MavType::MAV_TYPE_HELICOPTER => match custom_mode {
+            0 => Some("STABILIZE"),
+            1 => Some("ACRO"),
+            2 => Some("ALT_HOLD"),
+            3 => Some("AUTO"),
+            4 => Some("GUIDED"),
+            5 => Some("LOITER"),
+            6 => Some("RTL"),
+            7 => Some("CIRCLE"),
+            9 => Some("LAND"),
+            11 => Some("DRIFT"),
+            13 => Some("SPORT"),
+            14 => Some("FLIP"),
+            15 => Some("AUTOTUNE"),
+            16 => Some("POSHOLD"),
+            17 => Some("BRAKE"),
+            18 => Some("THROW"),
+            19 => Some("AVOID_ADSB"),
+            20 => Some("GUIDED_NOGPS"),
+            21 => Some("SMART_RTL"),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match name {
+        Some(name) => name.to_owned(),
+        None => format!("MODE({})", custom_mode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use tokio::net::UdpSocket;
+
+    use super::*;
+    use crate::pixhawk::transport::PixhawkAddress;
+
+    /// An autopilot on a loopback UDP socket that the tests script by hand.
+    struct FakeAutopilot {
+        sock: UdpSocket,
+        peer: Option<SocketAddr>,
+        codec: MavlinkCodec,
+        buf: BytesMut,
+        sequence: u8,
+    }
+
+    impl FakeAutopilot {
+        async fn bind() -> Self {
+            FakeAutopilot {
+                sock: UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+                peer: None,
+                codec: MavlinkCodec::new(),
+                buf: BytesMut::new(),
+                sequence: 0,
+            }
+        }
+
+        async fn connect_client(&self) -> PixhawkClient {
+            let address = self.sock.local_addr().unwrap().to_string();
+            let (_, cmd) = flume::unbounded();
+
+            PixhawkClient::connect(
+                Arc::new(Channels::detached()),
+                cmd,
+                PixhawkConfig {
+                    address: PixhawkAddress::UdpClient(address),
+                    mavlink: MavlinkVersion::V2,
+                    kind: Default::default(),
+                    params: Default::default(),
+                    message_intervals: Default::default(),
+                },
+            )
+            .await
+            .unwrap()
+        }
+
+        async fn recv(&mut self) -> apm::MavMessage {
+            loop {
+                if let Some((_, message)) = self.codec.decode(&mut self.buf).unwrap() {
+                    return message;
+                }
+
+                let mut chunk = [0; 1024];
+                let (n, peer) = self.sock.recv_from(&mut chunk).await.unwrap();
+
+                self.peer = Some(peer);
+                self.buf.extend_from_slice(&chunk[..n]);
+            }
+        }
+
+        /// Waits for a message from which `extract` returns a value, and
+        /// ignores everything else (such as the client's heartbeats).
+        async fn expect<T>(&mut self, extract: impl Fn(&apm::MavMessage) -> Option<T>) -> T {
+            loop {
+                if let Some(value) = extract(&self.recv().await) {
+                    return value;
+                }
+            }
+        }
+
+        async fn send(&mut self, message: apm::MavMessage) {
+            let header = MavHeader {
+                sequence: self.sequence,
+                system_id: 1,
+                component_id: 1,
+            };
+
+            self.sequence = self.sequence.wrapping_add(1);
+
+            let mut buf = Vec::new();
+            mavlink::write_versioned_msg(&mut buf, MavlinkVersion::V2, header, &message).unwrap();
+
+            let peer = self.peer.expect("client has not sent anything yet");
+            self.sock.send_to(&buf, peer).await.unwrap();
+        }
+    }
+
+    fn test_mission() -> Vec<MissionItem> {
+        [
+            (42.444, -76.5019, 50.),
+            (42.4452, -76.5031, 75.),
+            (42.4461, -76.5007, 100.),
+        ]
+        .iter()
+        .map(|&(latitude, longitude, altitude)| MissionItem {
+            command: common::MavCmd::MAV_CMD_NAV_WAYPOINT as u16,
+            frame: common::MavFrame::MAV_FRAME_GLOBAL_RELATIVE_ALT as u8,
+            params: [0.; 4],
+            latitude,
+            longitude,
+            altitude,
+            current: false,
+            autocontinue: true,
+        })
+        .collect()
+    }
+
+    fn assert_same_mission(actual: &[MissionItem], expected: &[MissionItem]) {
+        assert_eq!(actual.len(), expected.len());
+
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert_eq!(actual.command, expected.command);
+            assert_eq!(actual.frame, expected.frame);
+            assert_eq!(actual.latitude, expected.latitude);
+            assert_eq!(actual.longitude, expected.longitude);
+            assert_eq!(actual.altitude, expected.altitude);
+        }
+    }
+
+    fn mission_ack(result: common::MavMissionResult) -> apm::MavMessage {
+        apm::MavMessage::common(common::MavMessage::MISSION_ACK(common::MISSION_ACK_DATA {
+            target_system: 0,
+            target_component: 0,
+            mavtype: result,
+            mission_type: common::MavMissionType::MAV_MISSION_TYPE_MISSION,
+        }))
+    }
+
+    #[tokio::test]
+    async fn download_mission_retries_lost_request() {
+        let mut autopilot = FakeAutopilot::bind().await;
+        let mut client = autopilot.connect_client().await;
+        let mission = test_mission();
+
+        let autopilot = tokio::spawn({
+            let mission = mission.clone();
+
+            async move {
+                autopilot
+                    .expect(|message| match message {
+                        apm::MavMessage::common(common::MavMessage::MISSION_REQUEST_LIST(_)) => {
+                            Some(())
+                        }
+                        _ => None,
+                    })
+                    .await;
+
+                autopilot
+                    .send(apm::MavMessage::common(common::MavMessage::MISSION_COUNT(
+                        common::MISSION_COUNT_DATA {
+                            count: mission.len() as u16,
+                            target_system: 0,
+                            target_component: 0,
+                            mission_type: common::MavMissionType::MAV_MISSION_TYPE_MISSION,
+                        },
+                    )))
+                    .await;
+
+                let mut requests = vec![0; mission.len()];
+
+                loop {
+                    let seq = autopilot
+                        .expect(|message| match message {
+                            apm::MavMessage::common(common::MavMessage::MISSION_REQUEST_INT(
+                                data,
+                            )) => Some(Some(data.seq)),
+                            apm::MavMessage::common(common::MavMessage::MISSION_ACK(data)) => {
+                                assert_eq!(
+                                    data.mavtype,
+                                    common::MavMissionResult::MAV_MISSION_ACCEPTED
+                                );
+                                Some(None)
+                            }
+                            _ => None,
+                        })
+                        .await;
+
+                    let seq = match seq {
+                        Some(seq) => seq,
+                        None => break,
+                    };
+
+                    requests[seq as usize] += 1;
+
+                    // pretend that the first request for the second item was
+                    // lost, so the client has to ask again
+                    if seq == 1 && requests[1] == 1 {
+                        continue;
+                    }
+
+                    let item = mission[seq as usize].to_mavlink(seq).unwrap();
+                    autopilot
+                        .send(apm::MavMessage::common(
+                            common::MavMessage::MISSION_ITEM_INT(item),
+                        ))
+                        .await;
+                }
+
+                requests
+            }
+        });
+
+        let items = client.download_mission().await.unwrap();
+        let requests = autopilot.await.unwrap();
+
+        assert_same_mission(&items, &mission);
+        assert_eq!(requests, vec![1, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn upload_mission_resends_lost_item() {
+        let mut autopilot = FakeAutopilot::bind().await;
+        let mut client = autopilot.connect_client().await;
+        let mission = test_mission();
+
+        let autopilot = tokio::spawn(async move {
+            let count = autopilot
+                .expect(|message| match message {
+                    apm::MavMessage::common(common::MavMessage::MISSION_COUNT(data)) => {
+                        Some(data.count)
+                    }
+                    _ => None,
+                })
+                .await;
+
+            let mut items = Vec::new();
+
+            for seq in 0..count {
+                autopilot
+                    .send(apm::MavMessage::common(
+                        common::MavMessage::MISSION_REQUEST_INT(common::MISSION_REQUEST_INT_DATA {
+                            seq,
+                            target_system: 0,
+                            target_component: 0,
+                            mission_type: common::MavMissionType::MAV_MISSION_TYPE_MISSION,
+                        }),
+                    ))
+                    .await;
+
+                let item_with_seq = |message: &apm::MavMessage| match message {
+                    apm::MavMessage::common(common::MavMessage::MISSION_ITEM_INT(data))
+                        if data.seq == seq =>
+                    {
+                        Some(data.clone())
+                    }
+                    _ => None,
+                };
+
+                let mut item = autopilot.expect(item_with_seq).await;
+
+                // pretend that the first copy of the second item was lost, so
+                // the client has to send it again
+                if seq == 1 {
+                    item = autopilot.expect(item_with_seq).await;
+                }
+
+                items.push(MissionItem::from_mavlink(&item));
+            }
+
+            autopilot
+                .send(mission_ack(common::MavMissionResult::MAV_MISSION_ACCEPTED))
+                .await;
+
+            items
+        });
+
+        client.upload_mission(mission.clone()).await.unwrap();
+        let items = autopilot.await.unwrap();
+
+        assert_same_mission(&items, &mission);
+    }
+}
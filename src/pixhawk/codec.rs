@@ -0,0 +1,429 @@
+//! This module contains a MAVLink frame decoder that works with both v1 and v2
+//! frames, validates checksums, and keeps track of packet loss.
+
+use std::collections::HashMap;
+
+use bytes::{Buf, BytesMut};
+use mavlink::{ardupilotmega as apm, MavHeader, MavlinkVersion, Message};
+use serde::Serialize;
+use tokio_util::codec::Decoder;
+
+const MAGIC_V1: u8 = 0xFE;
+const MAGIC_V2: u8 = 0xFD;
+
+/// 1 byte magic + 1 byte payload len + 1 byte seq + 1 byte system id + 1 byte
+/// component id + 1 byte message id
+const HEADER_LEN_V1: usize = 6;
+
+/// 1 byte magic + 1 byte payload len + 1 byte incompat flags + 1 byte compat
+/// flags + 1 byte seq + 1 byte system id + 1 byte component id + 3 byte
+/// message id
+const HEADER_LEN_V2: usize = 10;
+
+const CHECKSUM_LEN: usize = 2;
+
+/// 1 byte link id + 6 byte timestamp + 6 byte signature
+const SIGNATURE_LEN: usize = 13;
+
+/// Incompatibility flag which indicates that the frame is signed.
+const IFLAG_SIGNED: u8 = 0x01;
+
+/// Packet statistics for a single system/component pair.
+#[derive(Default, Debug, Clone, Serialize)]
+pub struct ComponentStats {
+    /// Number of frames that were received successfully
+    pub received: u64,
+
+    /// Number of frames that were skipped according to the sequence numbers
+    pub lost: u64,
+
+    #[serde(skip)]
+    last_sequence: Option<u8>,
+}
+
+#[derive(Default, Debug, Clone, Serialize)]
+pub struct LinkStats {
+    /// Statistics keyed by `"<system id>/<component id>"`
+    pub components: HashMap<String, ComponentStats>,
+
+    /// Number of frames that were dropped because of a bad checksum
+    pub bad_checksum: u64,
+
+    /// Number of frames that had a valid checksum but could not be parsed
+    pub bad_payload: u64,
+
+    /// Number of frames that were dropped because they use incompatibility
+    /// flags that we do not understand, or because their message id is not
+    /// part of the dialect
+    pub unsupported: u64,
+
+    /// Number of bytes that were skipped while looking for the start of a
+    /// frame
+    pub skipped_bytes: u64,
+}
+
+/// Decodes MAVLink v1 and v2 frames from a stream of bytes. Frames with bad
+/// checksums are skipped, and gaps in the sequence numbers are counted as lost
+/// packets, but they do not cause any good frames to be dropped.
+#[derive(Default, Debug)]
+pub struct MavlinkCodec {
+    stats: LinkStats,
+}
+
+impl MavlinkCodec {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    fn record_sequence(&mut self, header: &MavHeader) {
+        let key = format!("{}/{}", header.system_id, header.component_id);
+        let stats = self.stats.components.entry(key).or_default();
+
+        if let Some(last_sequence) = stats.last_sequence {
+            let expected = last_sequence.wrapping_add(1);
+            let lost = header.sequence.wrapping_sub(expected);
+
+            if lost != 0 {
+                trace!(
+                    "sequence number jumped from {} to {}, assuming {} lost packets",
+                    last_sequence,
+                    header.sequence,
+                    lost
+                );
+            }
+
+            stats.lost += lost as u64;
+        }
+
+        stats.last_sequence = Some(header.sequence);
+        stats.received += 1;
+    }
+}
+
+impl Decoder for MavlinkCodec {
+    type Item = (MavHeader, apm::MavMessage);
+    type Error = anyhow::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            // skip forward to the next magic byte
+            match src.iter().position(|&b| b == MAGIC_V1 || b == MAGIC_V2) {
+                Some(position) => {
+                    self.stats.skipped_bytes += position as u64;
+                    src.advance(position);
+                }
+                None => {
+                    self.stats.skipped_bytes += src.len() as u64;
+                    src.clear();
+                    return Ok(None);
+                }
+            }
+
+            let version = if src[0] == MAGIC_V1 {
+                MavlinkVersion::V1
+            } else {
+                MavlinkVersion::V2
+            };
+
+            let header_len = match version {
+                MavlinkVersion::V1 => HEADER_LEN_V1,
+                MavlinkVersion::V2 => HEADER_LEN_V2,
+            };
+
+            if src.len() < header_len {
+                src.reserve(header_len - src.len());
+                return Ok(None);
+            }
+
+            let payload_len = src[1] as usize;
+
+            let (incompat_flags, header, message_id) = match version {
+                MavlinkVersion::V1 => (
+                    0,
+                    MavHeader {
+                        sequence: src[2],
+                        system_id: src[3],
+                        component_id: src[4],
+                    },
+                    src[5] as u32,
+                ),
+                MavlinkVersion::V2 => (
+                    src[2],
+                    MavHeader {
+                        sequence: src[4],
+                        system_id: src[5],
+                        component_id: src[6],
+                    },
+                    u32::from_le_bytes([src[7], src[8], src[9], 0]),
+                ),
+            };
+
+            if incompat_flags & !IFLAG_SIGNED != 0 {
+                trace!(
+                    "dropping frame with unsupported incompatibility flags {:02x}",
+                    incompat_flags
+                );
+                self.stats.unsupported += 1;
+                src.advance(1);
+                continue;
+            }
+
+            // we need the CRC_EXTRA byte for the message to validate the
+            // checksum, and the dialect reports 0 for messages it doesn't know
+            let extra_crc = apm::MavMessage::extra_crc(message_id);
+
+            if extra_crc == 0 {
+                trace!("dropping frame with unknown message id {}", message_id);
+                self.stats.unsupported += 1;
+                src.advance(1);
+                continue;
+            }
+
+            let signature_len = if incompat_flags & IFLAG_SIGNED != 0 {
+                SIGNATURE_LEN
+            } else {
+                0
+            };
+
+            let frame_len = header_len + payload_len + CHECKSUM_LEN + signature_len;
+
+            if src.len() < frame_len {
+                src.reserve(frame_len - src.len());
+                return Ok(None);
+            }
+
+            let checksum_start = header_len + payload_len;
+            let expected_checksum =
+                u16::from_le_bytes([src[checksum_start], src[checksum_start + 1]]);
+
+            let mut checksum = Crc::new();
+            checksum.accumulate(&src[1..checksum_start]);
+            checksum.accumulate(&[extra_crc]);
+
+            if checksum.value() != expected_checksum {
+                trace!(
+                    "dropping frame with bad checksum (message id {}, wanted {:04x}, got {:04x})",
+                    message_id,
+                    expected_checksum,
+                    checksum.value()
+                );
+                self.stats.bad_checksum += 1;
+
+                // the magic byte that we found might have been part of another
+                // frame's payload, so only skip past it
+                src.advance(1);
+                continue;
+            }
+
+            let frame = src.split_to(frame_len);
+            let payload = &frame[header_len..checksum_start];
+
+            self.record_sequence(&header);
+
+            match apm::MavMessage::parse(version, message_id, payload) {
+                Ok(message) => return Ok(Some((header, message))),
+                Err(err) => {
+                    debug!(
+                        "could not parse message with id {}: {:?}; payload: {:02x?}",
+                        message_id, err, payload
+                    );
+                    self.stats.bad_payload += 1;
+                    continue;
+                }
+            }
+        }
+    }
+}
+
+/// The CRC-16/MCRF4XX checksum that is used by MAVLink.
+struct Crc(u16);
+
+impl Crc {
+    fn new() -> Self {
+        Crc(0xFFFF)
+    }
+
+    fn accumulate(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let mut tmp = byte ^ (self.0 & 0xFF) as u8;
+            tmp ^= tmp << 4;
+            let tmp = tmp as u16;
+            self.0 = (self.0 >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4);
+        }
+    }
+
+    fn value(&self) -> u16 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mavlink::common;
+
+    /// HEARTBEAT from system 1, component 1 with sequence number 0, recorded
+    /// as a MAVLink v1 frame.
+    const HEARTBEAT_V1: &[u8] = &[
+        0xfe, 0x09, 0x00, 0x01, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x03, 0x51, 0x04, 0x03,
+        0x9c, 0x61,
+    ];
+
+    /// The same HEARTBEAT as a MAVLink v2 frame.
+    const HEARTBEAT_V2: &[u8] = &[
+        0xfd, 0x09, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01,
+        0x03, 0x51, 0x04, 0x03, 0x06, 0xa2,
+    ];
+
+    /// The same HEARTBEAT as a signed MAVLink v2 frame, followed by the
+    /// 13-byte signature.
+    const HEARTBEAT_V2_SIGNED: &[u8] = &[
+        0xfd, 0x09, 0x01, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01,
+        0x03, 0x51, 0x04, 0x03, 0xe1, 0x5a, 0x01, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x20, 0x21,
+        0x22, 0x23, 0x24, 0x25,
+    ];
+
+    /// The same HEARTBEAT as a MAVLink v2 frame with sequence number 3.
+    const HEARTBEAT_V2_SEQ_3: &[u8] = &[
+        0xfd, 0x09, 0x00, 0x00, 0x03, 0x01, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01,
+        0x03, 0x51, 0x04, 0x03, 0x27, 0x38,
+    ];
+
+    /// A MAVLink v2 frame with message id 0xabcdef, which isn't defined in
+    /// any dialect.
+    const UNKNOWN_V2: &[u8] = &[
+        0xfd, 0x09, 0x00, 0x00, 0x00, 0x01, 0x01, 0xef, 0xcd, 0xab, 0x04, 0x00, 0x00, 0x00, 0x01,
+        0x03, 0x51, 0x04, 0x03, 0x08, 0xd4,
+    ];
+
+    fn decode_all(codec: &mut MavlinkCodec, bytes: &[u8]) -> Vec<(MavHeader, apm::MavMessage)> {
+        let mut src = BytesMut::from(bytes);
+        let mut frames = Vec::new();
+
+        while let Some(frame) = codec.decode(&mut src).unwrap() {
+            frames.push(frame);
+        }
+
+        frames
+    }
+
+    fn assert_heartbeat(frame: &(MavHeader, apm::MavMessage), sequence: u8) {
+        let (header, message) = frame;
+
+        assert_eq!(header.system_id, 1);
+        assert_eq!(header.component_id, 1);
+        assert_eq!(header.sequence, sequence);
+
+        match message {
+            apm::MavMessage::common(common::MavMessage::HEARTBEAT(data)) => {
+                assert_eq!(data.custom_mode, 4);
+                assert_eq!(data.base_mode.bits(), 0x51);
+                assert_eq!(data.mavlink_version, 3);
+            }
+            other => panic!("expected a heartbeat, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_v1_frame() {
+        let mut codec = MavlinkCodec::new();
+        let frames = decode_all(&mut codec, HEARTBEAT_V1);
+
+        assert_eq!(frames.len(), 1);
+        assert_heartbeat(&frames[0], 0);
+        assert_eq!(codec.stats().components["1/1"].received, 1);
+    }
+
+    #[test]
+    fn decodes_v2_frame() {
+        let mut codec = MavlinkCodec::new();
+        let frames = decode_all(&mut codec, HEARTBEAT_V2);
+
+        assert_eq!(frames.len(), 1);
+        assert_heartbeat(&frames[0], 0);
+    }
+
+    #[test]
+    fn decodes_signed_v2_frame() {
+        let mut codec = MavlinkCodec::new();
+        let bytes = [HEARTBEAT_V2_SIGNED, HEARTBEAT_V2_SEQ_3].concat();
+        let frames = decode_all(&mut codec, &bytes);
+
+        // the signature must be consumed with the frame, otherwise the next
+        // frame is not found at the right offset
+        assert_eq!(frames.len(), 2);
+        assert_heartbeat(&frames[0], 0);
+        assert_heartbeat(&frames[1], 3);
+        assert_eq!(codec.stats().skipped_bytes, 0);
+    }
+
+    #[test]
+    fn drops_frame_with_bad_checksum() {
+        let mut corrupted = HEARTBEAT_V2.to_vec();
+        corrupted[12] ^= 0x01;
+
+        let mut codec = MavlinkCodec::new();
+        let bytes = [&corrupted[..], HEARTBEAT_V2_SEQ_3].concat();
+        let frames = decode_all(&mut codec, &bytes);
+
+        assert_eq!(frames.len(), 1);
+        assert_heartbeat(&frames[0], 3);
+        assert_eq!(codec.stats().bad_checksum, 1);
+    }
+
+    #[test]
+    fn skips_garbage_before_magic() {
+        let mut codec = MavlinkCodec::new();
+        let bytes = [&[0x00, 0x42, 0x13][..], HEARTBEAT_V1].concat();
+        let frames = decode_all(&mut codec, &bytes);
+
+        assert_eq!(frames.len(), 1);
+        assert_heartbeat(&frames[0], 0);
+        assert_eq!(codec.stats().skipped_bytes, 3);
+    }
+
+    #[test]
+    fn decodes_frame_split_across_reads() {
+        let mut codec = MavlinkCodec::new();
+        let mut src = BytesMut::from(&HEARTBEAT_V2[..8]);
+
+        assert!(codec.decode(&mut src).unwrap().is_none());
+
+        src.extend_from_slice(&HEARTBEAT_V2[8..]);
+        let frame = codec.decode(&mut src).unwrap().expect("expected a frame");
+
+        assert_heartbeat(&frame, 0);
+        assert!(src.is_empty());
+        assert_eq!(codec.stats().skipped_bytes, 0);
+    }
+
+    #[test]
+    fn counts_sequence_gaps() {
+        let mut codec = MavlinkCodec::new();
+        let bytes = [HEARTBEAT_V2, HEARTBEAT_V2_SEQ_3].concat();
+        let frames = decode_all(&mut codec, &bytes);
+
+        assert_eq!(frames.len(), 2);
+
+        let stats = &codec.stats().components["1/1"];
+        assert_eq!(stats.received, 2);
+        assert_eq!(stats.lost, 2);
+    }
+
+    #[test]
+    fn counts_unknown_messages_as_unsupported() {
+        let mut codec = MavlinkCodec::new();
+        let bytes = [UNKNOWN_V2, HEARTBEAT_V2].concat();
+        let frames = decode_all(&mut codec, &bytes);
+
+        assert_eq!(frames.len(), 1);
+        assert_heartbeat(&frames[0], 0);
+        assert_eq!(codec.stats().unsupported, 1);
+        assert_eq!(codec.stats().bad_checksum, 0);
+    }
+}
@@ -0,0 +1,137 @@
+use std::{path::PathBuf, str::FromStr};
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::Command;
+
+use super::{codec::LinkStats, MissionItem};
+
+pub type PixhawkCommand = Command<PixhawkRequest, PixhawkResponse>;
+
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[clap(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum PixhawkRequest {
+    /// set a parameter on the pixhawk
+    SetParam {
+        id: String,
+        value: f64,
+
+        /// the type of the parameter (f32, u8, i8, u16, i16, u32, i32, u64,
+        /// i64)
+        #[clap(long, default_value = "f32")]
+        #[serde(default)]
+        kind: ParamKind,
+    },
+
+    /// read a parameter from the pixhawk
+    GetParam { id: String },
+
+    /// download every parameter from the pixhawk and save them to a .param
+    /// file
+    // this writes to an arbitrary path, so it is only available from the REPL
+    // and not from the HTTP API
+    #[serde(skip)]
+    DumpParams {
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+    },
+
+    /// send an arbitrary COMMAND_LONG to the pixhawk
+    Command {
+        /// the numeric id of the MAV_CMD
+        command: u16,
+
+        /// up to 7 parameters for the command; missing parameters are zero
+        params: Vec<f32>,
+    },
+
+    /// set the interval at which the pixhawk sends a message
+    SetMessageInterval {
+        /// the numeric id of the message
+        message_id: u32,
+
+        /// the interval between messages in microseconds; -1 disables the
+        /// message and 0 restores the default rate
+        interval: f32,
+    },
+
+    /// trigger the camera attached to the pixhawk
+    TriggerCamera,
+
+    /// show packet statistics for the link to the pixhawk
+    LinkStats,
+
+    /// download the current mission from the pixhawk
+    DownloadMission,
+
+    /// replace the mission on the pixhawk; this is only available through the
+    /// plane server
+    #[clap(skip)]
+    UploadMission { items: Vec<MissionItem> },
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamKind {
+    F32,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+}
+
+impl Default for ParamKind {
+    fn default() -> Self {
+        ParamKind::F32
+    }
+}
+
+impl ParamKind {
+    /// Checks whether a value read back from the Pixhawk is the same as the
+    /// value that was written, taking into account that every parameter is
+    /// transmitted as an `f32`.
+    pub fn matches(&self, wanted: f64, actual: f64) -> bool {
+        match self {
+            ParamKind::F32 => {
+                let (wanted, actual) = (wanted as f32, actual as f32);
+                (wanted - actual).abs() <= f32::EPSILON * wanted.abs().max(1.)
+            }
+            _ => wanted.round() as i64 == actual.round() as i64,
+        }
+    }
+}
+
+impl FromStr for ParamKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "f32" | "float" | "real32" => Self::F32,
+            "u8" => Self::U8,
+            "i8" => Self::I8,
+            "u16" => Self::U16,
+            "i16" => Self::I16,
+            "u32" => Self::U32,
+            "i32" => Self::I32,
+            "u64" => Self::U64,
+            "i64" => Self::I64,
+            _ => bail!("invalid parameter type"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum PixhawkResponse {
+    Unit,
+    Param { id: String, value: f64 },
+    CommandResult { result: String },
+    ParamDump { path: PathBuf, count: usize },
+    LinkStats(LinkStats),
+    Mission { items: Vec<MissionItem> },
+}
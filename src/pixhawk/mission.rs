@@ -0,0 +1,72 @@
+use anyhow::Context;
+use mavlink::common;
+use num_traits::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+/// A single item of a mission, as transferred with `MISSION_ITEM_INT`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MissionItem {
+    /// The numeric id of the MAV_CMD that this item executes
+    pub command: u16,
+
+    /// The numeric id of the MAV_FRAME that the coordinates are in
+    pub frame: u8,
+
+    pub params: [f32; 4],
+
+    /// Latitude in degrees, or the x coordinate in local frames
+    pub latitude: f64,
+
+    /// Longitude in degrees, or the y coordinate in local frames
+    pub longitude: f64,
+
+    /// Altitude in meters, or the z coordinate in local frames
+    pub altitude: f32,
+
+    #[serde(default)]
+    pub current: bool,
+
+    #[serde(default = "default_autocontinue")]
+    pub autocontinue: bool,
+}
+
+fn default_autocontinue() -> bool {
+    true
+}
+
+impl MissionItem {
+    pub fn from_mavlink(data: &common::MISSION_ITEM_INT_DATA) -> Self {
+        MissionItem {
+            command: data.command.to_u16().unwrap_or_default(),
+            frame: data.frame.to_u8().unwrap_or_default(),
+            params: [data.param1, data.param2, data.param3, data.param4],
+            latitude: data.x as f64 / 1e7,
+            longitude: data.y as f64 / 1e7,
+            altitude: data.z,
+            current: data.current != 0,
+            autocontinue: data.autocontinue != 0,
+        }
+    }
+
+    pub fn to_mavlink(&self, seq: u16) -> anyhow::Result<common::MISSION_ITEM_INT_DATA> {
+        Ok(common::MISSION_ITEM_INT_DATA {
+            param1: self.params[0],
+            param2: self.params[1],
+            param3: self.params[2],
+            param4: self.params[3],
+            x: (self.latitude * 1e7).round() as i32,
+            y: (self.longitude * 1e7).round() as i32,
+            z: self.altitude,
+            seq,
+            command: common::MavCmd::from_u16(self.command)
+                .with_context(|| format!("unknown command id {}", self.command))?,
+            target_system: 0,
+            target_component: 0,
+            frame: common::MavFrame::from_u8(self.frame)
+                .with_context(|| format!("unknown frame id {}", self.frame))?,
+            current: self.current as u8,
+            autocontinue: self.autocontinue as u8,
+            mission_type: common::MavMissionType::MAV_MISSION_TYPE_MISSION,
+        })
+    }
+}
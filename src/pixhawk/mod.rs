@@ -1,5 +1,15 @@
 pub mod client;
+pub mod codec;
+pub mod command;
+pub mod mission;
+pub mod params;
+pub mod sim;
 pub mod state;
+pub mod transport;
 
 pub use client::*;
+pub use command::*;
+pub use mission::MissionItem;
+pub use params::{ParamList, ParamValue};
+pub use sim::PixhawkKind;
 pub use state::*;
@@ -0,0 +1,67 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Context;
+use mavlink::common::MavParamType;
+use serde::Serialize;
+use tokio::{fs::File, io::AsyncWriteExt};
+
+/// A parameter as reported by the Pixhawk in a `PARAM_VALUE` message.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamValue {
+    pub index: u16,
+    pub value: f32,
+    #[serde(serialize_with = "serialize_param_type")]
+    pub kind: MavParamType,
+}
+
+/// All of the parameters on the Pixhawk, sorted by name.
+pub type ParamList = BTreeMap<String, ParamValue>;
+
+fn serialize_param_type<S>(this: &MavParamType, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::ser::Serializer,
+{
+    serializer.collect_str(&format_args!("{:?}", this))
+}
+
+/// Converts the null-padded parameter name used by MAVLink into a string.
+pub fn decode_param_id(param_id: &[char; 16]) -> String {
+    param_id.iter().take_while(|&&c| c != '\0').collect()
+}
+
+/// Converts a parameter name into the null-padded form used by MAVLink.
+pub fn encode_param_id(id: &str) -> [char; 16] {
+    let mut param_id: [char; 16] = ['\0'; 16];
+    for (index, character) in id.chars().take(16).enumerate() {
+        param_id[index] = character;
+    }
+    param_id
+}
+
+/// Writes a parameter list to a file in the format used by MAVProxy and
+/// accepted by QGroundControl and Mission Planner, i.e. one `NAME VALUE` pair
+/// per line.
+pub async fn write_param_file(path: impl AsRef<Path>, params: &ParamList) -> anyhow::Result<()> {
+    let mut contents = String::new();
+
+    for (id, param) in params {
+        let value = match param.kind {
+            MavParamType::MAV_PARAM_TYPE_REAL32 | MavParamType::MAV_PARAM_TYPE_REAL64 => {
+                format!("{:.6}", param.value)
+            }
+            _ => format!("{}", param.value as i64),
+        };
+
+        contents.push_str(&format!("{:<16} {}\n", id, value));
+    }
+
+    let mut file = File::create(path.as_ref())
+        .await
+        .context("failed to create parameter file")?;
+
+    file.write_all(contents.as_bytes())
+        .await
+        .context("failed to write parameter file")?;
+
+    Ok(())
+}
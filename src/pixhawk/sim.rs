@@ -0,0 +1,725 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::PI,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context;
+use bytes::BytesMut;
+use mavlink::{ardupilotmega as apm, common, MavHeader, MavlinkVersion};
+use serde::Deserialize;
+use tokio::net::UdpSocket;
+use tokio_util::codec::Decoder;
+
+use crate::{util::run_loop, Channels};
+
+use super::{
+    codec::MavlinkCodec,
+    params::{decode_param_id, encode_param_id},
+    transport::PixhawkAddress,
+};
+
+/// How often the simulated autopilot sends its position and attitude.
+const TELEMETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How many telemetry messages are sent for each heartbeat.
+const TELEMETRY_PER_HEARTBEAT: u32 = 20;
+
+/// Radius of the Earth in meters.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// The flight mode that is reported in heartbeats, which is `AUTO` for
+/// ArduPlane.
+const SIMULATED_MODE: u32 = 10;
+
+/// The simulated autopilot uses the usual system and component IDs of an
+/// ArduPilot autopilot.
+const SYSTEM_ID: u8 = 1;
+const COMPONENT_ID: u8 = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PixhawkKind {
+    /// A real Pixhawk, or anything else that speaks MAVLink (SITL, MAVProxy)
+    Hardware,
+
+    /// A simulated autopilot that runs inside of the plane system
+    Simulated(SimulatedPixhawkConfig),
+}
+
+impl Default for PixhawkKind {
+    fn default() -> Self {
+        PixhawkKind::Hardware
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulatedPixhawkConfig {
+    /// Latitude of the point that the plane flies around, in degrees
+    pub latitude: f64,
+
+    /// Longitude of the point that the plane flies around, in degrees
+    pub longitude: f64,
+
+    /// Altitude above the home point in meters
+    #[serde(default = "default_altitude")]
+    pub altitude: f32,
+
+    /// Altitude of the home point above mean sea level in meters
+    #[serde(default)]
+    pub home_altitude: f32,
+
+    /// Groundspeed in meters per second
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+
+    pub pattern: SimulatedPattern,
+}
+
+fn default_altitude() -> f32 {
+    100.
+}
+
+fn default_speed() -> f32 {
+    20.
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SimulatedPattern {
+    /// Circles the home point at the given radius in meters
+    Loiter { radius: f32 },
+
+    /// Flies north-south passes over a rectangle centered on the home point.
+    /// All distances are in meters.
+    Lawnmower {
+        width: f32,
+        height: f32,
+        spacing: f32,
+    },
+}
+
+/// Where the plane is relative to the home point.
+#[derive(Debug, Clone, Copy)]
+struct FlightState {
+    /// Meters east of the home point
+    east: f32,
+
+    /// Meters north of the home point
+    north: f32,
+
+    /// Heading in radians clockwise from north
+    heading: f32,
+
+    /// Roll in radians, positive when banking right
+    roll: f32,
+}
+
+/// A straight leg of a route, from one point to another. Points are in meters
+/// east and north of the home point.
+type Leg = ((f32, f32), (f32, f32), f32);
+
+/// A pattern that has been validated and laid out, ready to be flown.
+#[derive(Debug, Clone)]
+enum Route {
+    Loiter { radius: f32 },
+    Legs { legs: Vec<Leg>, total: f32 },
+}
+
+impl SimulatedPattern {
+    /// Checks the pattern and lays out its waypoints.
+    fn build(&self) -> anyhow::Result<Route> {
+        match *self {
+            SimulatedPattern::Loiter { radius } => Ok(Route::Loiter {
+                radius: radius.max(1.),
+            }),
+            SimulatedPattern::Lawnmower {
+                width,
+                height,
+                spacing,
+            } => {
+                if !spacing.is_finite() || spacing <= 0. {
+                    bail!("lawnmower spacing must be positive, got {}", spacing);
+                }
+
+                if !width.is_finite() || !height.is_finite() || width < 0. || height < 0. {
+                    bail!(
+                        "lawnmower width and height must be finite and not negative, got {} x {}",
+                        width,
+                        height
+                    );
+                }
+
+                let passes = (width / spacing).floor() as usize + 1;
+
+                let mut waypoints = Vec::with_capacity(passes * 2);
+
+                for pass in 0..passes {
+                    let east = -width / 2. + pass as f32 * spacing;
+                    let (start, end) = if pass % 2 == 0 {
+                        (-height / 2., height / 2.)
+                    } else {
+                        (height / 2., -height / 2.)
+                    };
+
+                    waypoints.push((east, start));
+                    waypoints.push((east, end));
+                }
+
+                // after the last pass, fly back to the start of the first
+                let legs = waypoints
+                    .iter()
+                    .zip(waypoints.iter().cycle().skip(1))
+                    .map(|(&from, &to)| {
+                        let length = (to.0 - from.0).hypot(to.1 - from.1);
+                        (from, to, length)
+                    })
+                    .collect::<Vec<_>>();
+
+                let total = legs.iter().map(|(_, _, length)| length).sum::<f32>();
+
+                Ok(Route::Legs { legs, total })
+            }
+        }
+    }
+}
+
+impl Route {
+    /// Returns where the plane is after flying the route for `elapsed`
+    /// seconds at `speed` meters per second.
+    fn flight_state(&self, speed: f32, elapsed: f32) -> FlightState {
+        match self {
+            Route::Loiter { radius } => {
+                let radius = *radius;
+                let angle = speed * elapsed / radius;
+
+                FlightState {
+                    east: radius * angle.sin(),
+                    north: radius * angle.cos(),
+                    // flying clockwise, so we are always headed 90 degrees
+                    // clockwise of the direction from the center to the plane
+                    heading: (angle + PI / 2.).rem_euclid(2. * PI),
+                    // bank angle for a coordinated turn
+                    roll: (speed * speed / (radius * 9.81)).atan(),
+                }
+            }
+            Route::Legs { legs, total } => {
+                if *total <= 0. {
+                    return FlightState {
+                        east: 0.,
+                        north: 0.,
+                        heading: 0.,
+                        roll: 0.,
+                    };
+                }
+
+                let mut distance = (speed * elapsed).rem_euclid(*total);
+
+                for &(from, to, length) in legs {
+                    if distance <= length && length > 0. {
+                        let t = distance / length;
+
+                        return FlightState {
+                            east: from.0 + (to.0 - from.0) * t,
+                            north: from.1 + (to.1 - from.1) * t,
+                            heading: (to.0 - from.0).atan2(to.1 - from.1).rem_euclid(2. * PI),
+                            roll: 0.,
+                        };
+                    }
+
+                    distance -= length;
+                }
+
+                // rounding error can leave us just past the end of the last
+                // leg, which is the start of the first one
+                let (from, to, _) = legs[0];
+
+                FlightState {
+                    east: from.0,
+                    north: from.1,
+                    heading: (to.0 - from.0).atan2(to.1 - from.1).rem_euclid(2. * PI),
+                    roll: 0.,
+                }
+            }
+        }
+    }
+}
+
+/// An autopilot that flies a fixed pattern and sends MAVLink to the address
+/// that the `PixhawkClient` is listening on, so that the rest of the system
+/// can be tested without a Pixhawk or SITL.
+pub struct SimulatedPixhawk {
+    sock: UdpSocket,
+    target: SocketAddr,
+    version: MavlinkVersion,
+    codec: MavlinkCodec,
+    buf: BytesMut,
+    sequence: u8,
+    config: SimulatedPixhawkConfig,
+    route: Route,
+    started: Instant,
+    params: BTreeMap<String, (f32, common::MavParamType)>,
+    message_intervals: HashMap<u16, i32>,
+    images: u16,
+}
+
+impl SimulatedPixhawk {
+    pub async fn new(
+        address: &PixhawkAddress,
+        version: MavlinkVersion,
+        config: SimulatedPixhawkConfig,
+    ) -> anyhow::Result<Self> {
+        let route = config
+            .pattern
+            .build()
+            .context("invalid simulated flight pattern")?;
+
+        let target = match address {
+            PixhawkAddress::UdpServer(addr) => tokio::net::lookup_host(addr)
+                .await
+                .context("invalid pixhawk address")?
+                .next()
+                .context("invalid pixhawk address")?,
+            _ => bail!("the simulated pixhawk requires a udp:// pixhawk address"),
+        };
+
+        // the client listens on all interfaces, but we need a specific one to
+        // send to
+        let target = if target.ip().is_unspecified() {
+            SocketAddr::new([127, 0, 0, 1].into(), target.port())
+        } else {
+            target
+        };
+
+        let sock = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .context("failed to bind simulated pixhawk socket")?;
+
+        info!("simulating pixhawk, sending to {}", target);
+
+        Ok(SimulatedPixhawk {
+            sock,
+            target,
+            version,
+            codec: MavlinkCodec::new(),
+            buf: BytesMut::with_capacity(1024),
+            sequence: 0,
+            config,
+            route,
+            started: Instant::now(),
+            params: BTreeMap::new(),
+            message_intervals: HashMap::new(),
+            images: 0,
+        })
+    }
+
+    async fn send(&mut self, message: apm::MavMessage) -> anyhow::Result<()> {
+        let header = MavHeader {
+            sequence: self.sequence,
+            system_id: SYSTEM_ID,
+            component_id: COMPONENT_ID,
+        };
+
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let mut buf = Vec::with_capacity(280);
+        mavlink::write_versioned_msg(&mut buf, self.version, header, &message)?;
+
+        // the client may not be listening yet, and UDP does not care if
+        // nobody receives our packets
+        if let Err(err) = self.sock.send_to(&buf, self.target).await {
+            trace!("simulated pixhawk failed to send: {}", err);
+        }
+
+        Ok(())
+    }
+
+    fn time_boot_ms(&self) -> u32 {
+        self.started.elapsed().as_millis() as u32
+    }
+
+    fn flight_state(&self) -> FlightState {
+        self.route
+            .flight_state(self.config.speed, self.started.elapsed().as_secs_f32())
+    }
+
+    /// Converts a position relative to the home point into latitude and
+    /// longitude in degrees.
+    fn to_global(&self, state: &FlightState) -> (f64, f64) {
+        let lat = self.config.latitude + (state.north as f64 / EARTH_RADIUS).to_degrees();
+        let lon = self.config.longitude
+            + (state.east as f64 / (EARTH_RADIUS * self.config.latitude.to_radians().cos()))
+                .to_degrees();
+
+        (lat, lon)
+    }
+
+    async fn send_heartbeat(&mut self) -> anyhow::Result<()> {
+        self.send(apm::MavMessage::common(common::MavMessage::HEARTBEAT(
+            common::HEARTBEAT_DATA {
+                custom_mode: SIMULATED_MODE,
+                mavtype: common::MavType::MAV_TYPE_FIXED_WING,
+                autopilot: common::MavAutopilot::MAV_AUTOPILOT_ARDUPILOTMEGA,
+                base_mode: common::MavModeFlag::MAV_MODE_FLAG_CUSTOM_MODE_ENABLED
+                    | common::MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED,
+                system_status: common::MavState::MAV_STATE_ACTIVE,
+                mavlink_version: 3,
+            },
+        )))
+        .await
+    }
+
+    async fn send_telemetry(&mut self) -> anyhow::Result<()> {
+        let state = self.flight_state();
+        let (lat, lon) = self.to_global(&state);
+
+        let (sin_heading, cos_heading) = state.heading.sin_cos();
+        let speed = self.config.speed;
+
+        self.send(apm::MavMessage::common(
+            common::MavMessage::GLOBAL_POSITION_INT(common::GLOBAL_POSITION_INT_DATA {
+                time_boot_ms: self.time_boot_ms(),
+                lat: (lat * 1e7) as i32,
+                lon: (lon * 1e7) as i32,
+                alt: ((self.config.home_altitude + self.config.altitude) * 1e3) as i32,
+                relative_alt: (self.config.altitude * 1e3) as i32,
+                // north, east, down in cm/s
+                vx: (speed * cos_heading * 100.) as i16,
+                vy: (speed * sin_heading * 100.) as i16,
+                vz: 0,
+                hdg: (state.heading.to_degrees() * 100.) as u16 % 36000,
+            }),
+        ))
+        .await?;
+
+        self.send(apm::MavMessage::common(common::MavMessage::ATTITUDE(
+            common::ATTITUDE_DATA {
+                time_boot_ms: self.time_boot_ms(),
+                roll: state.roll,
+                pitch: 0.,
+                // ardupilot reports yaw in the range -pi to pi
+                yaw: if state.heading > PI {
+                    state.heading - 2. * PI
+                } else {
+                    state.heading
+                },
+                rollspeed: 0.,
+                pitchspeed: 0.,
+                yawspeed: 0.,
+            },
+        )))
+        .await
+    }
+
+    async fn send_camera_feedback(&mut self) -> anyhow::Result<()> {
+        let state = self.flight_state();
+        let (lat, lon) = self.to_global(&state);
+
+        let img_idx = self.images;
+        self.images = self.images.wrapping_add(1);
+
+        debug!("simulated pixhawk triggered camera (image {})", img_idx);
+
+        self.send(apm::MavMessage::CAMERA_FEEDBACK(
+            apm::CAMERA_FEEDBACK_DATA {
+                time_usec: SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_micros() as u64,
+                lat: (lat * 1e7) as i32,
+                lng: (lon * 1e7) as i32,
+                alt_msl: self.config.home_altitude + self.config.altitude,
+                alt_rel: self.config.altitude,
+                roll: state.roll.to_degrees(),
+                pitch: 0.,
+                yaw: state.heading.to_degrees(),
+                foc_len: 0.,
+                img_idx,
+                target_system: 0,
+                cam_idx: 0,
+                flags: apm::CameraFeedbackFlags::CAMERA_FEEDBACK_PHOTO,
+                completed_captures: self.images,
+            },
+        ))
+        .await
+    }
+
+    async fn send_param(&mut self, index: usize) -> anyhow::Result<()> {
+        let count = self.params.len() as u16;
+
+        let (id, (value, kind)) = match self.params.iter().nth(index) {
+            Some((id, param)) => (id.clone(), *param),
+            None => return Ok(()),
+        };
+
+        self.send(apm::MavMessage::common(common::MavMessage::PARAM_VALUE(
+            common::PARAM_VALUE_DATA {
+                param_value: value,
+                param_count: count,
+                param_index: index as u16,
+                param_id: encode_param_id(&id),
+                param_type: kind,
+            },
+        )))
+        .await
+    }
+
+    async fn send_ack(&mut self, command: common::MavCmd) -> anyhow::Result<()> {
+        self.send(apm::MavMessage::common(common::MavMessage::COMMAND_ACK(
+            common::COMMAND_ACK_DATA {
+                command,
+                result: common::MavResult::MAV_RESULT_ACCEPTED,
+                progress: 0,
+                result_param2: 0,
+                target_system: 0,
+                target_component: 0,
+            },
+        )))
+        .await
+    }
+
+    /// Responds to a message from the client like ArduPilot would.
+    async fn handle(&mut self, message: apm::MavMessage) -> anyhow::Result<()> {
+        let message = match message {
+            apm::MavMessage::common(message) => message,
+            _ => return Ok(()),
+        };
+
+        match message {
+            common::MavMessage::PARAM_SET(data) => {
+                let id = decode_param_id(&data.param_id);
+                self.params
+                    .insert(id.clone(), (data.param_value, data.param_type));
+
+                let index = self.params.keys().position(|k| k == &id).unwrap();
+                self.send_param(index).await?;
+            }
+            common::MavMessage::PARAM_REQUEST_READ(data) => {
+                let index = if data.param_index < 0 {
+                    let id = decode_param_id(&data.param_id);
+                    self.params.keys().position(|k| k == &id)
+                } else {
+                    Some(data.param_index as usize)
+                };
+
+                if let Some(index) = index {
+                    self.send_param(index).await?;
+                }
+            }
+            common::MavMessage::PARAM_REQUEST_LIST(_) => {
+                for index in 0..self.params.len() {
+                    self.send_param(index).await?;
+                }
+            }
+            common::MavMessage::COMMAND_LONG(data) => {
+                self.send_ack(data.command).await?;
+
+                match data.command {
+                    common::MavCmd::MAV_CMD_DO_DIGICAM_CONTROL => {
+                        self.send_camera_feedback().await?;
+                    }
+                    common::MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL => {
+                        self.message_intervals
+                            .insert(data.param1 as u16, data.param2 as i32);
+                    }
+                    common::MavCmd::MAV_CMD_GET_MESSAGE_INTERVAL => {
+                        let message_id = data.param1 as u16;
+                        let interval_us = self
+                            .message_intervals
+                            .get(&message_id)
+                            .copied()
+                            .unwrap_or(TELEMETRY_INTERVAL.as_micros() as i32);
+
+                        self.send(apm::MavMessage::common(
+                            common::MavMessage::MESSAGE_INTERVAL(common::MESSAGE_INTERVAL_DATA {
+                                interval_us,
+                                message_id,
+                            }),
+                        ))
+                        .await?;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub async fn run(mut self, channels: Arc<Channels>) -> anyhow::Result<()> {
+        let mut interrupt_recv = channels.interrupt.subscribe();
+
+        run_loop!(
+            async move {
+                let mut interval = tokio::time::interval(TELEMETRY_INTERVAL);
+                let mut ticks = 0u32;
+                let mut chunk = [0; 1024];
+
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {
+                            if ticks % TELEMETRY_PER_HEARTBEAT == 0 {
+                                self.send_heartbeat().await?;
+                            }
+
+                            ticks = ticks.wrapping_add(1);
+                            self.send_telemetry().await?;
+                        }
+                        n = self.sock.recv(&mut chunk) => {
+                            // errors here are usually ICMP port unreachable
+                            // from before the client started listening
+                            let n = match n {
+                                Ok(n) => n,
+                                Err(_) => continue,
+                            };
+
+                            self.buf.extend_from_slice(&chunk[..n]);
+
+                            while let Some((_, message)) = self.codec.decode(&mut self.buf)? {
+                                self.handle(message).await?;
+                            }
+                        }
+                    }
+                }
+            },
+            interrupt_recv.recv()
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::config::{PixhawkConfig, PixhawkMessageIntervalConfig, PixhawkParamConfig},
+        pixhawk::{client::PixhawkClient, state::PixhawkEvent, ParamKind},
+    };
+
+    fn sim_config(pattern: SimulatedPattern) -> SimulatedPixhawkConfig {
+        SimulatedPixhawkConfig {
+            latitude: 42.44,
+            longitude: -76.48,
+            altitude: 100.,
+            home_altitude: 0.,
+            speed: 20.,
+            pattern,
+        }
+    }
+
+    #[test]
+    fn lawnmower_requires_positive_spacing() {
+        for &spacing in &[0., -50., f32::NAN] {
+            let pattern = SimulatedPattern::Lawnmower {
+                width: 400.,
+                height: 600.,
+                spacing,
+            };
+
+            assert!(pattern.build().is_err(), "spacing {} was accepted", spacing);
+        }
+
+        let pattern = SimulatedPattern::Lawnmower {
+            width: 400.,
+            height: 600.,
+            spacing: 50.,
+        };
+
+        match pattern.build().unwrap() {
+            // 9 passes with 2 legs each
+            Route::Legs { legs, .. } => assert_eq!(legs.len(), 18),
+            route => panic!("expected legs, got {:?}", route),
+        }
+    }
+
+    #[tokio::test]
+    async fn client_completes_handshakes_with_simulator() {
+        // find a free port for the client to listen on
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = PixhawkAddress::UdpServer(format!("127.0.0.1:{}", port));
+
+        let channels = Arc::new(Channels::detached());
+        let mut events = channels.pixhawk_event.subscribe();
+
+        let sim = SimulatedPixhawk::new(
+            &address,
+            MavlinkVersion::V2,
+            sim_config(SimulatedPattern::Loiter { radius: 150. }),
+        )
+        .await
+        .unwrap();
+        let sim = tokio::spawn(sim.run(channels.clone()));
+
+        let mut params = BTreeMap::new();
+        params.insert(
+            "CAM_DURATION".to_owned(),
+            PixhawkParamConfig {
+                kind: ParamKind::F32,
+                value: 10.,
+            },
+        );
+
+        let (_, cmd) = flume::unbounded();
+        let mut client = PixhawkClient::connect(
+            channels.clone(),
+            cmd,
+            PixhawkConfig {
+                address,
+                mavlink: MavlinkVersion::V2,
+                kind: PixhawkKind::Hardware,
+                params,
+                message_intervals: vec![PixhawkMessageIntervalConfig {
+                    message_id: 30,
+                    interval_us: 20000.,
+                }],
+            },
+        )
+        .await
+        .unwrap();
+
+        client.init().await.unwrap();
+
+        let (value, _) = client.get_param("CAM_DURATION").await.unwrap();
+        assert_eq!(value, 10.);
+
+        assert_eq!(client.get_message_interval(30).await.unwrap(), 20000);
+
+        let all_params = client.fetch_all_params().await.unwrap();
+        assert_eq!(all_params["CAM_DURATION"].value, 10.);
+
+        let telemetry = async {
+            let (mut position, mut attitude) = (false, false);
+
+            while !(position && attitude) {
+                client.recv().await.unwrap();
+
+                while let Ok(event) = events.try_recv() {
+                    match event {
+                        PixhawkEvent::Gps { position: p, .. } => {
+                            assert!((p.point.y() - 42.44).abs() < 0.01);
+                            assert!((p.point.x() + 76.48).abs() < 0.01);
+                            position = true;
+                        }
+                        PixhawkEvent::Orientation { .. } => attitude = true,
+                        _ => {}
+                    }
+                }
+            }
+        };
+
+        tokio::time::timeout(Duration::from_secs(5), telemetry)
+            .await
+            .expect("no telemetry from the simulated pixhawk");
+
+        let _ = channels.interrupt.send(());
+        sim.await.unwrap().unwrap();
+    }
+}
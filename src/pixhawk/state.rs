@@ -1,6 +1,6 @@
 use std::time::SystemTime;
 
-use crate::state::{Attitude, Point3D};
+use crate::state::{Attitude, Battery, GpsStatus, Point3D, Quaternion, Speed};
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -16,7 +16,8 @@ pub struct Telemetry {
     pub attitude_timestamp: Option<SystemTime>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum PixhawkEvent {
     Image {
         time: SystemTime,
@@ -35,7 +36,34 @@ pub enum PixhawkEvent {
     Orientation {
         attitude: Attitude,
     },
+    OrientationQuaternion {
+        attitude: Quaternion,
+    },
+    GpsStatus(GpsStatus),
+    Speed(Speed),
+    Battery(Battery),
+    MissionCurrent {
+        /// The index of the mission item that the plane is flying to
+        seq: u16,
+    },
+    /// Sent whenever the mission is downloaded from or uploaded to the
+    /// autopilot.
+    Mission {
+        items: Vec<super::MissionItem>,
+    },
+    StatusText {
+        severity: mavlink::common::MavSeverity,
+        text: String,
+    },
+    /// Sent whenever a heartbeat is received from the autopilot.
+    Heartbeat {
+        /// The name of the current flight mode, e.g. `AUTO` or `FBWA`
+        mode: String,
+        armed: bool,
+        system_status: mavlink::common::MavState,
+    },
+    /// Sent when the autopilot has not sent a heartbeat in a while.
+    LinkLost,
+    /// Sent when the autopilot sends a heartbeat after the link was lost.
+    LinkRestored,
 }
-
-// TODO
-pub type PixhawkCommand = ();
@@ -0,0 +1,186 @@
+use std::{convert::TryFrom, fmt::Display, path::PathBuf, str::FromStr};
+
+use serde::Deserialize;
+
+// udp socket that waits for a peer (i.e. mavproxy) to send to it
+pub mod udp;
+
+// tcp connection to the autopilot or a telemetry router
+pub mod tcp;
+
+// direct connection to the autopilot's usb or telemetry port
+pub mod serial;
+
+pub use serial::*;
+pub use tcp::*;
+pub use udp::*;
+
+/// The baud rate that is used when a serial address does not specify one.
+const DEFAULT_BAUD_RATE: u32 = 115200;
+
+/// A byte-level connection to the Pixhawk. The MAVLink framing is done by the
+/// `PixhawkClient`, so transports only need to move bytes around.
+#[async_trait]
+pub trait MavlinkTransport: Send {
+    /// Writes all of `buf` to the Pixhawk.
+    async fn send(&mut self, buf: &[u8]) -> anyhow::Result<()>;
+
+    /// Reads some bytes from the Pixhawk into `buf` and returns how many were
+    /// read.
+    async fn recv(&mut self, buf: &mut [u8]) -> anyhow::Result<usize>;
+}
+
+/// Describes how to connect to the Pixhawk. This is parsed from a URL such as
+/// `udp://0.0.0.0:14550`, `tcp://127.0.0.1:5760` or
+/// `serial:///dev/ttyACM0:921600`. A plain socket address is treated as a UDP
+/// server for backwards compatibility.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum PixhawkAddress {
+    /// Listens on a UDP port and locks on to the first peer that sends a
+    /// packet to it.
+    UdpServer(String),
+
+    /// Sends UDP packets to a remote address.
+    UdpClient(String),
+
+    /// Connects to a remote TCP server.
+    TcpClient(String),
+
+    /// Opens a serial port.
+    Serial { path: PathBuf, baud_rate: u32 },
+}
+
+impl PixhawkAddress {
+    pub async fn connect(&self) -> anyhow::Result<Box<dyn MavlinkTransport>> {
+        Ok(match self {
+            PixhawkAddress::UdpServer(addr) => Box::new(UdpServerTransport::bind(addr).await?),
+            PixhawkAddress::UdpClient(addr) => Box::new(UdpClientTransport::connect(addr).await?),
+            PixhawkAddress::TcpClient(addr) => Box::new(TcpClientTransport::connect(addr).await?),
+            PixhawkAddress::Serial { path, baud_rate } => {
+                Box::new(SerialTransport::open(path, *baud_rate)?)
+            }
+        })
+    }
+}
+
+impl FromStr for PixhawkAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = match s.split_once("://") {
+            Some(parts) => parts,
+            None => return Ok(PixhawkAddress::UdpServer(s.to_owned())),
+        };
+
+        if rest.is_empty() {
+            bail!("pixhawk address {:?} is missing a host or path", s);
+        }
+
+        Ok(match scheme {
+            "udp" | "udpin" => PixhawkAddress::UdpServer(rest.to_owned()),
+            "udpout" => PixhawkAddress::UdpClient(rest.to_owned()),
+            "tcp" => PixhawkAddress::TcpClient(rest.to_owned()),
+            "serial" => {
+                let (path, baud_rate) = split_baud_rate(rest);
+
+                PixhawkAddress::Serial {
+                    path: PathBuf::from(path),
+                    baud_rate,
+                }
+            }
+            _ => bail!("unknown pixhawk address scheme {:?}", scheme),
+        })
+    }
+}
+
+/// Splits the baud rate off the end of a serial address. The baud rate is
+/// optional, so the part after the last colon is only treated as a baud rate
+/// if it is a number.
+fn split_baud_rate(address: &str) -> (&str, u32) {
+    if let Some((path, baud_rate)) = address.rsplit_once(':') {
+        if let Ok(baud_rate) = baud_rate.parse::<u32>() {
+            return (path, baud_rate);
+        }
+    }
+
+    (address, DEFAULT_BAUD_RATE)
+}
+
+impl TryFrom<String> for PixhawkAddress {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for PixhawkAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PixhawkAddress::UdpServer(addr) => write!(f, "udp://{}", addr),
+            PixhawkAddress::UdpClient(addr) => write!(f, "udpout://{}", addr),
+            PixhawkAddress::TcpClient(addr) => write!(f, "tcp://{}", addr),
+            PixhawkAddress::Serial { path, baud_rate } => {
+                write!(f, "serial://{}:{}", path.display(), baud_rate)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(address: &str) -> PixhawkAddress {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_network_addresses() {
+        assert_eq!(
+            parse("udp://0.0.0.0:14550"),
+            PixhawkAddress::UdpServer("0.0.0.0:14550".to_owned())
+        );
+        assert_eq!(
+            parse("udpout://192.168.1.10:14550"),
+            PixhawkAddress::UdpClient("192.168.1.10:14550".to_owned())
+        );
+        assert_eq!(
+            parse("tcp://127.0.0.1:5760"),
+            PixhawkAddress::TcpClient("127.0.0.1:5760".to_owned())
+        );
+    }
+
+    #[test]
+    fn parses_serial_addresses() {
+        assert_eq!(
+            parse("serial:///dev/ttyACM0:921600"),
+            PixhawkAddress::Serial {
+                path: PathBuf::from("/dev/ttyACM0"),
+                baud_rate: 921600,
+            }
+        );
+        assert_eq!(
+            parse("serial:///dev/ttyACM0"),
+            PixhawkAddress::Serial {
+                path: PathBuf::from("/dev/ttyACM0"),
+                baud_rate: DEFAULT_BAUD_RATE,
+            }
+        );
+    }
+
+    #[test]
+    fn treats_bare_address_as_udp_server() {
+        assert_eq!(
+            parse("0.0.0.0:14550"),
+            PixhawkAddress::UdpServer("0.0.0.0:14550".to_owned())
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_scheme() {
+        assert!("http://127.0.0.1:5760".parse::<PixhawkAddress>().is_err());
+        assert!("tcp://".parse::<PixhawkAddress>().is_err());
+    }
+}
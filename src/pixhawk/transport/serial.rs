@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use anyhow::Context;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::{Serial, SerialPortSettings};
+
+use super::MavlinkTransport;
+
+pub struct SerialTransport {
+    port: Serial,
+}
+
+impl SerialTransport {
+    pub fn open<P: AsRef<Path>>(path: P, baud_rate: u32) -> anyhow::Result<Self> {
+        let settings = SerialPortSettings {
+            baud_rate,
+            ..Default::default()
+        };
+
+        let port = Serial::from_path(path.as_ref(), &settings)
+            .with_context(|| format!("failed to open serial port {}", path.as_ref().display()))?;
+
+        info!(
+            "opened serial port {} at {} baud",
+            path.as_ref().display(),
+            baud_rate
+        );
+
+        Ok(Self { port })
+    }
+}
+
+#[async_trait]
+impl MavlinkTransport for SerialTransport {
+    async fn send(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+        self.port.write_all(buf).await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> anyhow::Result<usize> {
+        Ok(self.port.read(buf).await?)
+    }
+}
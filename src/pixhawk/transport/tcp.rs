@@ -0,0 +1,43 @@
+use anyhow::Context;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::MavlinkTransport;
+
+pub struct TcpClientTransport {
+    stream: TcpStream,
+}
+
+impl TcpClientTransport {
+    pub async fn connect(addr: &str) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .context("failed to connect to pixhawk")?;
+
+        stream.set_nodelay(true)?;
+
+        info!("connected to {}", addr);
+
+        Ok(Self { stream })
+    }
+}
+
+#[async_trait]
+impl MavlinkTransport for TcpClientTransport {
+    async fn send(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+        self.stream.write_all(buf).await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> anyhow::Result<usize> {
+        let n = self.stream.read(buf).await?;
+
+        if n == 0 {
+            bail!("pixhawk closed the tcp connection");
+        }
+
+        Ok(n)
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::net::UdpSocket;
+
+use super::MavlinkTransport;
+
+pub struct UdpServerTransport {
+    sock: UdpSocket,
+}
+
+impl UdpServerTransport {
+    /// Binds to `addr` and waits for a packet from a peer. All traffic is then
+    /// locked to that peer.
+    pub async fn bind(addr: &str) -> anyhow::Result<Self> {
+        let sock = UdpSocket::bind(addr)
+            .await
+            .context("failed to connect to pixhawk")?;
+
+        debug!("waiting for packet from mavproxy");
+
+        let (_, remote_addr) =
+            tokio::time::timeout(Duration::from_secs(60), sock.recv_from(&mut []))
+                .await
+                .context("timed out while waiting for packet from mavproxy")?
+                .context("error retrieving packet from mavproxy")?;
+
+        info!(
+            "received packet from {:?}, locking to this address",
+            remote_addr
+        );
+
+        sock.connect(remote_addr)
+            .await
+            .context("failed to lock to address")?;
+
+        Ok(Self { sock })
+    }
+}
+
+#[async_trait]
+impl MavlinkTransport for UdpServerTransport {
+    async fn send(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+        self.sock.send(buf).await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> anyhow::Result<usize> {
+        Ok(self.sock.recv(buf).await?)
+    }
+}
+
+pub struct UdpClientTransport {
+    sock: UdpSocket,
+}
+
+impl UdpClientTransport {
+    /// Binds to an ephemeral port and sends all traffic to `addr`.
+    pub async fn connect(addr: &str) -> anyhow::Result<Self> {
+        let sock = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .context("failed to bind udp socket")?;
+
+        sock.connect(addr)
+            .await
+            .context("failed to connect to pixhawk")?;
+
+        info!("sending udp packets to {}", addr);
+
+        Ok(Self { sock })
+    }
+}
+
+#[async_trait]
+impl MavlinkTransport for UdpClientTransport {
+    async fn send(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+        self.sock.send(buf).await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> anyhow::Result<usize> {
+        Ok(self.sock.recv(buf).await?)
+    }
+}
@@ -0,0 +1,222 @@
+pub mod replay;
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+    sync::broadcast::{self, error::RecvError},
+};
+
+use crate::{
+    camera::main::{CameraClientEvent, ErrorMode, LensState},
+    cli::config::RecorderConfig,
+    gimbal::GimbalEvent,
+    pixhawk::state::PixhawkEvent,
+    Channels,
+};
+
+/// How often buffered records are flushed to the disk. This bounds how much
+/// of the flight is lost if the plane system crashes or loses power.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A single line of a flight log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    #[serde(serialize_with = "crate::util::serialize_time")]
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    pub event: RecordedEvent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "source", content = "data", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Pixhawk(PixhawkEvent),
+    Camera(RecordedCameraEvent),
+    Gimbal(GimbalEvent),
+
+    /// The recorder fell behind and missed `skipped` events from `channel`.
+    Gap {
+        channel: String,
+        skipped: u64,
+    },
+}
+
+/// A camera event without the image data, which is saved separately by the
+/// image task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedCameraEvent {
+    Capture {
+        #[serde(serialize_with = "crate::util::serialize_time")]
+        timestamp: chrono::DateTime<chrono::Local>,
+        #[serde(default)]
+        lens: Option<LensState>,
+    },
+    Download {
+        image_name: String,
+        image_size: usize,
+        #[serde(serialize_with = "crate::util::serialize_time_option")]
+        cc_timestamp: Option<chrono::DateTime<chrono::Local>>,
+        lens: Option<LensState>,
+    },
+    Error {
+        error: ErrorMode,
+    },
+}
+
+impl From<CameraClientEvent> for RecordedCameraEvent {
+    fn from(event: CameraClientEvent) -> Self {
+        match event {
+            CameraClientEvent::Capture { timestamp, lens } => {
+                RecordedCameraEvent::Capture { timestamp, lens }
+            }
+            CameraClientEvent::Download {
+                image_name,
+                image_data,
+                cc_timestamp,
+                lens,
+            } => RecordedCameraEvent::Download {
+                image_name,
+                image_size: image_data.len(),
+                cc_timestamp,
+                lens,
+            },
+            CameraClientEvent::Error(error) => RecordedCameraEvent::Error { error },
+        }
+    }
+}
+
+/// Writes records to newline-delimited JSON files, starting a new file when
+/// the current one gets too large.
+struct LogWriter {
+    dir: PathBuf,
+    max_file_size: u64,
+    file: Option<BufWriter<File>>,
+    file_size: u64,
+}
+
+impl LogWriter {
+    async fn write(&mut self, record: &Record) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record).context("failed to serialize record")?;
+        line.push(b'\n');
+
+        if self.file.is_some() && self.file_size + line.len() as u64 > self.max_file_size {
+            self.close().await?;
+        }
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let path = self.dir.join(format!(
+                    "flight_{}.ndjson",
+                    chrono::Local::now().format("%F_%H-%M-%S%.3f")
+                ));
+
+                info!("recording flight data to '{}'", path.to_string_lossy());
+
+                let file = File::create(&path)
+                    .await
+                    .context("failed to create flight log")?;
+
+                self.file_size = 0;
+                self.file.insert(BufWriter::new(file))
+            }
+        };
+
+        file.write_all(&line)
+            .await
+            .context("failed to write to flight log")?;
+
+        self.file_size += line.len() as u64;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.flush().await.context("failed to flush flight log")?;
+        }
+
+        Ok(())
+    }
+
+    async fn close(&mut self) -> anyhow::Result<()> {
+        self.flush().await?;
+        self.file = None;
+        Ok(())
+    }
+}
+
+pub async fn run(channels: Arc<Channels>, config: RecorderConfig) -> anyhow::Result<()> {
+    let mut interrupt_recv = channels.interrupt.subscribe();
+    let mut pixhawk_recv = channels.pixhawk_event.subscribe();
+    let mut camera_recv = channels.camera_event.subscribe();
+    let mut gimbal_recv = channels.gimbal_event.subscribe();
+
+    tokio::fs::create_dir_all(&config.path)
+        .await
+        .context("could not create flight log directory")?;
+
+    let mut writer = LogWriter {
+        dir: config.path,
+        max_file_size: config.max_file_size_mb * 1024 * 1024,
+        file: None,
+        file_size: 0,
+    };
+
+    let mut flush_interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        let event = tokio::select! {
+            Some(event) = recv_event(&mut pixhawk_recv, "pixhawk", RecordedEvent::Pixhawk) => event,
+            Some(event) = recv_event(&mut camera_recv, "camera", |event| {
+                RecordedEvent::Camera(event.into())
+            }) => event,
+            Some(event) = recv_event(&mut gimbal_recv, "gimbal", RecordedEvent::Gimbal) => event,
+            _ = flush_interval.tick() => {
+                writer.flush().await?;
+                continue;
+            }
+            _ = interrupt_recv.recv() => break,
+        };
+
+        let record = Record {
+            timestamp: chrono::Local::now(),
+            event,
+        };
+
+        writer.write(&record).await?;
+    }
+
+    writer.close().await?;
+
+    Ok(())
+}
+
+/// Receives the next event from `recv`. If the recorder fell behind and events
+/// were dropped, a gap is recorded instead so that it is visible in the log.
+/// Returns `None` if the channel is closed.
+async fn recv_event<T: Clone>(
+    recv: &mut broadcast::Receiver<T>,
+    channel: &str,
+    convert: impl FnOnce(T) -> RecordedEvent,
+) -> Option<RecordedEvent> {
+    match recv.recv().await {
+        Ok(event) => Some(convert(event)),
+        Err(RecvError::Lagged(skipped)) => {
+            warn!(
+                "recorder fell behind and skipped {} {} events",
+                skipped, channel
+            );
+
+            Some(RecordedEvent::Gap {
+                channel: channel.to_owned(),
+                skipped,
+            })
+        }
+        Err(RecvError::Closed) => None,
+    }
+}
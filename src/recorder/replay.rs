@@ -0,0 +1,137 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, BufReader},
+    time::Instant,
+};
+
+use crate::Channels;
+
+use super::{Record, RecordedEvent};
+
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    /// A flight log, or a folder of flight logs which are replayed in order
+    path: PathBuf,
+
+    /// How fast to replay the log relative to real time
+    speed: f64,
+}
+
+impl ReplayConfig {
+    pub fn new(path: PathBuf, speed: f64) -> anyhow::Result<Self> {
+        if speed.is_nan() || speed <= 0. {
+            bail!("replay speed must be positive");
+        }
+
+        Ok(ReplayConfig { path, speed })
+    }
+}
+
+/// Lists the flight logs at `path`. The recorder names logs after the time
+/// that they were started, so sorting them by name puts them in order.
+async fn list_logs(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !tokio::fs::metadata(path)
+        .await
+        .context("could not open flight log")?
+        .is_dir()
+    {
+        return Ok(vec![path.to_owned()]);
+    }
+
+    let mut logs = vec![];
+    let mut entries = tokio::fs::read_dir(path)
+        .await
+        .context("could not read flight log folder")?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if path.extension().map_or(false, |ext| ext == "ndjson") {
+            logs.push(path);
+        }
+    }
+
+    logs.sort();
+
+    Ok(logs)
+}
+
+/// Publishes the Pixhawk and gimbal events in a flight log as if they were
+/// being received live. Camera events are skipped because the log does not
+/// contain the images.
+pub async fn run(channels: Arc<Channels>, config: ReplayConfig) -> anyhow::Result<()> {
+    let mut interrupt_recv = channels.interrupt.subscribe();
+    let interrupt_fut = interrupt_recv.recv();
+
+    let loop_fut = async move {
+        let logs = list_logs(&config.path).await?;
+
+        if logs.is_empty() {
+            bail!("no flight logs found in {:?}", config.path);
+        }
+
+        // the time at which the replay started, and the time in the log
+        // that corresponds to it
+        let mut start: Option<(Instant, chrono::DateTime<chrono::Local>)> = None;
+
+        for log in logs {
+            info!("replaying flight log '{}'", log.to_string_lossy());
+
+            let file = File::open(&log)
+                .await
+                .context("could not open flight log")?;
+            let mut lines = BufReader::new(file).lines();
+            let mut line_number = 0;
+
+            while let Some(line) = lines.next_line().await? {
+                line_number += 1;
+
+                let record: Record = match serde_json::from_str(&line) {
+                    Ok(record) => record,
+                    Err(err) => {
+                        warn!("skipping invalid record on line {}: {}", line_number, err);
+                        continue;
+                    }
+                };
+
+                let (start_instant, start_timestamp) =
+                    *start.get_or_insert_with(|| (Instant::now(), record.timestamp));
+
+                let offset = (record.timestamp - start_timestamp)
+                    .to_std()
+                    .unwrap_or_default()
+                    .div_f64(config.speed);
+
+                tokio::time::sleep_until(start_instant + offset).await;
+
+                match record.event {
+                    RecordedEvent::Pixhawk(event) => {
+                        let _ = channels.pixhawk_event.send(event);
+                    }
+                    RecordedEvent::Gimbal(event) => {
+                        let _ = channels.gimbal_event.send(event);
+                    }
+                    RecordedEvent::Camera(_) | RecordedEvent::Gap { .. } => {}
+                }
+            }
+        }
+
+        info!("finished replaying flight log");
+
+        Result::<(), anyhow::Error>::Ok(())
+    };
+
+    futures::pin_mut!(loop_fut);
+    futures::pin_mut!(interrupt_fut);
+
+    match futures::future::select(interrupt_fut, loop_fut).await {
+        futures::future::Either::Left(_) => Ok(()),
+        futures::future::Either::Right((result, _)) => result,
+    }
+}
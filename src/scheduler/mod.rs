@@ -1,12 +1,59 @@
+use futures::channel::oneshot::Canceled;
+use geo::algorithm::{contains::Contains, haversine_distance::HaversineDistance};
 use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
 
-use crate::{state::Telemetry, Channels};
+use crate::{
+    camera::main::{CameraClientEvent, CameraCommandRequest, CameraCommandResponse, LensState},
+    geometry,
+    image::ImageClientEvent,
+    state::Telemetry,
+    util::ReceiverExt,
+    Channels, Command,
+};
 
-use std::{path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for an image to be downloaded after the camera has been
+/// triggered before giving up on it.
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Distance in meters at which an ROI's priority is halved.
+const DISTANCE_SCALE: f32 = 100.0;
 
 struct SchedulerState {
     active_rois: Vec<Roi>,
+    captures: Vec<Capture>,
+    next_capture_id: usize,
+
+    /// The state of the lens as of the most recent capture. The scheduler
+    /// doesn't change the zoom, so this is used to predict the footprint of
+    /// the next capture.
+    lens: LensState,
+
+    /// The capture that the camera has been told to take, but which has not
+    /// been downloaded yet.
+    pending_capture: Option<PendingCapture>,
+}
+
+struct PendingCapture {
+    /// IDs of ROIs which were in the camera footprint when the camera was
+    /// triggered
+    rois: Vec<usize>,
+
+    started: Instant,
+
+    /// Time at which the camera reported the capture. Set once the capture
+    /// event arrives, and used to find the image that belongs to this capture.
+    capture_timestamp: Option<chrono::DateTime<chrono::Local>>,
+
+    /// Receives the camera's response to the capture command. Set to `None`
+    /// once the response has been received.
+    response: Option<futures::channel::oneshot::Receiver<anyhow::Result<CameraCommandResponse>>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -37,9 +84,10 @@ pub struct Capture {
     /// IDs of ROIs which are present in this capture
     rois: Vec<usize>,
 
+    #[serde(serialize_with = "crate::util::serialize_time")]
     timestamp: chrono::DateTime<chrono::Local>,
 
-    telemetry: Telemetry,
+    telemetry: Option<Telemetry>,
 
     file: PathBuf,
 }
@@ -63,12 +111,18 @@ pub async fn run(
     cmd_recv: flume::Receiver<SchedulerCommand>,
 ) -> anyhow::Result<()> {
     let mut interrupt_recv = channels.interrupt.subscribe();
-    let _telemetry_recv = channels.pixhawk_telemetry.clone();
+    let telemetry_recv = channels.pixhawk_telemetry.clone();
+    let mut image_recv = channels.image_event.subscribe();
+    let mut camera_recv = channels.camera_event.subscribe();
     let interrupt_fut = interrupt_recv.recv();
 
     let loop_fut = async move {
         let mut state = SchedulerState {
             active_rois: vec![],
+            captures: vec![],
+            next_capture_id: 0,
+            lens: LensState::default(),
+            pending_capture: None,
         };
 
         let mut interval = tokio::time::interval(Duration::from_millis(50));
@@ -77,11 +131,21 @@ pub async fn run(
         loop {
             tokio::select! {
                 _ = interval.tick() => {
-                    // if let Some(telemetry) = telemetry_recv.borrow().clone() {
-                    //     // update the angle of the gimbal according to current
-                    //     // telemetry information
-                    //     run_update(&mut state, telemetry).await?;
-                    // }
+                    let telemetry = telemetry_recv.borrow().clone();
+
+                    if let Some(telemetry) = telemetry {
+                        run_update(&mut state, &channels, telemetry).await?;
+                    }
+                }
+                camera_evt = camera_recv.recv_skip() => {
+                    if let Some(CameraClientEvent::Capture { timestamp, lens }) = camera_evt {
+                        run_capture(&mut state, timestamp, lens);
+                    }
+                }
+                image_evt = image_recv.recv_skip() => {
+                    if let Some(image_evt) = image_evt {
+                        run_image(&mut state, image_evt);
+                    }
                 }
                 cmd = cmd_recv.recv_async() => {
                     run_command(&mut state, cmd?).await?;
@@ -102,16 +166,189 @@ pub async fn run(
     Ok(())
 }
 
-async fn run_update(state: &mut SchedulerState, _telemetry: Telemetry) -> anyhow::Result<()> {
+async fn run_update(
+    state: &mut SchedulerState,
+    channels: &Channels,
+    telemetry: Telemetry,
+) -> anyhow::Result<()> {
+    if let Some(pending) = &mut state.pending_capture {
+        if let Some(response) = &mut pending.response {
+            match response.try_recv() {
+                Ok(None) => {}
+                Ok(Some(Ok(_))) => {
+                    debug!("camera confirmed capture, waiting for download");
+                    pending.response = None;
+                }
+                Ok(Some(Err(err))) => {
+                    warn!("scheduled capture failed: {:?}", err);
+                    state.pending_capture = None;
+                    return Ok(());
+                }
+                Err(Canceled) => {
+                    warn!("camera client dropped scheduled capture");
+                    state.pending_capture = None;
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Some(pending) = &state.pending_capture {
+            if pending.started.elapsed() > CAPTURE_TIMEOUT {
+                warn!("timed out waiting for scheduled capture to be downloaded");
+                state.pending_capture = None;
+            }
+        }
+
+        // only one capture can be in flight at a time
+        return Ok(());
+    }
+
+    let position = telemetry.position.point;
+
     // give all of the ROIs a priority
-    state.active_rois.iter().map(|roi| {
-        // prioritize ROIs that have not been photographed much
-        let _rarity = 1. / roi.captures.len() as f32;
+    let best = state
+        .active_rois
+        .iter()
+        .map(|roi| {
+            // prioritize ROIs that have not been photographed much
+            let rarity = 1. / (roi.captures.len() + 1) as f32;
+
+            // prioritize ROIs that are close to the plane
+            let distance = position.haversine_distance(&roi.location);
+            let proximity = 1. / (1. + distance / DISTANCE_SCALE);
+
+            (roi, rarity * proximity, distance)
+        })
+        .max_by(|(_, a, _), (_, b, _)| a.total_cmp(b));
+
+    let (best, priority, distance) = match best {
+        Some(best) => best,
+        None => return Ok(()),
+    };
+
+    // if the camera can see the horizon, the footprint is unbounded, so don't
+    // try to take a picture of anything
+    let footprint = match geometry::footprint(&telemetry, &state.lens.intrinsics()) {
+        Some(footprint) => footprint.polygon(),
+        None => return Ok(()),
+    };
+
+    if !footprint.contains(&best.location) {
+        return Ok(());
+    }
+
+    // every ROI in the footprint will be in the image, not just the one we are
+    // aiming for
+    let rois = state
+        .active_rois
+        .iter()
+        .filter(|roi| footprint.contains(&roi.location))
+        .map(|roi| roi.id)
+        .collect::<Vec<_>>();
+
+    info!(
+        "triggering capture of roi {} (priority {:.3}, distance {:.1} m), rois in view: {:?}",
+        best.id, priority, distance, rois
+    );
+
+    let (cmd, chan) = Command::new(CameraCommandRequest::Capture);
+
+    if let Err(err) = channels.camera_cmd.send(cmd) {
+        warn!("camera client not available: {}", err);
+        return Ok(());
+    }
+
+    state.pending_capture = Some(PendingCapture {
+        rois,
+        started: Instant::now(),
+        capture_timestamp: None,
+        response: Some(chan),
     });
 
     Ok(())
 }
 
-async fn run_command(_state: &mut SchedulerState, _cmd: SchedulerCommand) -> anyhow::Result<()> {
+/// Records the state of the lens, and links the camera's capture event to the
+/// pending capture, if there is one that has not been linked yet.
+fn run_capture(
+    state: &mut SchedulerState,
+    timestamp: chrono::DateTime<chrono::Local>,
+    lens: Option<LensState>,
+) {
+    if let Some(lens) = lens {
+        state.lens = lens;
+    }
+
+    if let Some(pending) = &mut state.pending_capture {
+        if pending.capture_timestamp.is_none() {
+            pending.capture_timestamp = Some(timestamp);
+        }
+    }
+}
+
+/// Links a downloaded image to the pending capture, if the image was taken by
+/// that capture. Images from other captures, such as ones that were triggered
+/// manually, are ignored.
+fn run_image(state: &mut SchedulerState, image_evt: ImageClientEvent) {
+    let timestamp = match &state.pending_capture {
+        Some(PendingCapture {
+            capture_timestamp: Some(timestamp),
+            ..
+        }) if image_evt.cc_timestamp == Some(*timestamp) => *timestamp,
+        _ => return,
+    };
+
+    let pending = match state.pending_capture.take() {
+        Some(pending) => pending,
+        None => return,
+    };
+
+    let capture = Capture {
+        id: state.next_capture_id,
+        rois: pending.rois,
+        timestamp,
+        telemetry: image_evt.telemetry,
+        file: image_evt.file,
+    };
+
+    state.next_capture_id += 1;
+
+    debug!(
+        "recorded capture {} of rois {:?} in {:?}",
+        capture.id, capture.rois, capture.file
+    );
+
+    for roi in &mut state.active_rois {
+        if capture.rois.contains(&roi.id) {
+            roi.captures.push(capture.clone());
+        }
+    }
+
+    state.captures.push(capture);
+}
+
+async fn run_command(state: &mut SchedulerState, cmd: SchedulerCommand) -> anyhow::Result<()> {
+    match cmd {
+        SchedulerCommand::AddROIs { rois, tx } => {
+            for roi in rois {
+                if let Some(existing) = state.active_rois.iter_mut().find(|r| r.id == roi.id) {
+                    // keep the captures that we already have for this ROI
+                    existing.location = roi.location;
+                    existing.kind = roi.kind;
+                } else {
+                    state.active_rois.push(roi);
+                }
+            }
+
+            let _ = tx.send(());
+        }
+        SchedulerCommand::GetROIs { tx } => {
+            let _ = tx.send(state.active_rois.clone());
+        }
+        SchedulerCommand::GetCaptures { tx } => {
+            let _ = tx.send(state.captures.clone());
+        }
+    }
+
     Ok(())
 }
@@ -1,11 +1,15 @@
 use futures::StreamExt;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{convert::Infallible, net::SocketAddr, sync::Arc};
 use tokio::sync::oneshot;
-use warp::{self, Filter};
+use warp::{self, http::StatusCode, Filter, Reply};
 
+use crate::camera::main::{
+    CameraCommandFileRequest, CameraCommandRequest, CameraCommandStorageRequest,
+};
+use crate::pixhawk::{MissionItem, PixhawkRequest};
 use crate::scheduler::{Roi, SchedulerCommand};
-use crate::Channels;
+use crate::{Channels, Command};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct AddROIs {
@@ -20,6 +24,11 @@ enum ClientType {
     ADLC,
 }
 
+#[derive(Deserialize, Debug, Copy, Clone)]
+struct FileListQuery {
+    parent: Option<u32>,
+}
+
 pub async fn serve(channels: Arc<Channels>, address: SocketAddr) -> anyhow::Result<()> {
     info!("initializing server");
 
@@ -39,23 +48,231 @@ pub async fn serve(channels: Arc<Channels>, address: SocketAddr) -> anyhow::Resu
                 async move {
                     debug!("received ROIs: {:?}", &body);
 
-                    let (tx, rx) = oneshot::channel();
+                    scheduler_reply(&channels, |tx| SchedulerCommand::AddROIs {
+                        rois: body.rois,
+                        tx,
+                    })
+                    .await
+                }
+            }
+        });
+
+    let route_roi_list = warp::path!("api" / "roi").and(warp::get()).then({
+        let channels = channels.clone();
+        move || {
+            let channels = channels.clone();
+            async move { scheduler_reply(&channels, |tx| SchedulerCommand::GetROIs { tx }).await }
+        }
+    });
 
-                    channels
-                        .scheduler_cmd
-                        .send(SchedulerCommand::AddROIs {
-                            rois: body.rois,
-                            tx,
-                        })
-                        .unwrap();
+    let route_captures =
+        warp::path!("api" / "captures").and(warp::get()).then({
+            let channels = channels.clone();
+            move || {
+                let channels = channels.clone();
+                async move {
+                    scheduler_reply(&channels, |tx| SchedulerCommand::GetCaptures { tx }).await
+                }
+            }
+        });
+
+    let route_pixhawk = warp::path!("api" / "pixhawk" / "command")
+        .and(warp::post())
+        .and(warp::body::json())
+        .then({
+            let channels = channels.clone();
+            move |body: PixhawkRequest| {
+                let channels = channels.clone();
+                async move {
+                    debug!("received pixhawk command: {:?}", &body);
+                    pixhawk_reply(&channels, body).await
+                }
+            }
+        });
 
-                    rx.await.unwrap();
+    let route_mission = warp::path!("api" / "mission").and(warp::get()).then({
+        let channels = channels.clone();
+        move || {
+            let channels = channels.clone();
+            async move { pixhawk_reply(&channels, PixhawkRequest::DownloadMission).await }
+        }
+    });
 
-                    warp::reply()
+    let route_mission_upload = warp::path!("api" / "mission")
+        .and(warp::post())
+        .and(warp::body::json())
+        .then({
+            let channels = channels.clone();
+            move |items: Vec<MissionItem>| {
+                let channels = channels.clone();
+                async move {
+                    debug!("received mission with {} items", items.len());
+                    pixhawk_reply(&channels, PixhawkRequest::UploadMission { items }).await
                 }
             }
         });
 
+    let route_camera = warp::path!("api" / "camera" / "command")
+        .and(warp::post())
+        .and(warp::body::json())
+        .then({
+            let channels = channels.clone();
+            move |body: CameraCommandRequest| {
+                let channels = channels.clone();
+                async move {
+                    debug!("received camera command: {:?}", &body);
+                    camera_reply(&channels, body).await
+                }
+            }
+        });
+
+    let route_camera_capture = warp::path!("api" / "camera" / "capture")
+        .and(warp::post())
+        .then({
+            let channels = channels.clone();
+            move || {
+                let channels = channels.clone();
+                async move { camera_reply(&channels, CameraCommandRequest::Capture).await }
+            }
+        });
+
+    let route_camera_cc = warp::path!("api" / "camera" / "cc" / String)
+        .and(warp::post())
+        .then({
+            let channels = channels.clone();
+            move |action: String| {
+                let channels = channels.clone();
+                async move {
+                    match tagged_request("action", action, serde_json::Map::new()) {
+                        Ok(request) => {
+                            camera_reply(
+                                &channels,
+                                CameraCommandRequest::ContinuousCapture(request),
+                            )
+                            .await
+                        }
+                        Err(err) => err,
+                    }
+                }
+            }
+        });
+
+    let route_camera_get = warp::path!("api" / "camera" / "property" / String)
+        .and(warp::get())
+        .then({
+            let channels = channels.clone();
+            move |property: String| {
+                let channels = channels.clone();
+                async move {
+                    match tagged_request("property", property, serde_json::Map::new()) {
+                        Ok(request) => {
+                            camera_reply(&channels, CameraCommandRequest::Get(request)).await
+                        }
+                        Err(err) => err,
+                    }
+                }
+            }
+        });
+
+    let route_camera_set = warp::path!("api" / "camera" / "property" / String)
+        .and(warp::put())
+        .and(warp::body::json())
+        .then({
+            let channels = channels.clone();
+            move |property: String, fields: serde_json::Map<String, serde_json::Value>| {
+                let channels = channels.clone();
+                async move {
+                    match tagged_request("property", property, fields) {
+                        Ok(request) => {
+                            camera_reply(&channels, CameraCommandRequest::Set(request)).await
+                        }
+                        Err(err) => err,
+                    }
+                }
+            }
+        });
+
+    let route_camera_storage = warp::path!("api" / "camera" / "storage")
+        .and(warp::get())
+        .then({
+            let channels = channels.clone();
+            move || {
+                let channels = channels.clone();
+                async move {
+                    let request = CameraCommandRequest::Storage(CameraCommandStorageRequest::List);
+                    camera_reply(&channels, request).await
+                }
+            }
+        });
+
+    let route_camera_files = warp::path!("api" / "camera" / "files")
+        .and(warp::get())
+        .and(warp::query())
+        .then({
+            let channels = channels.clone();
+            move |query: FileListQuery| {
+                let channels = channels.clone();
+                async move {
+                    let request = CameraCommandRequest::File(CameraCommandFileRequest::List {
+                        parent: query.parent,
+                    });
+                    camera_reply(&channels, request).await
+                }
+            }
+        });
+
+    let route_camera_download = warp::path!("api" / "camera" / "files" / u32 / "download")
+        .and(warp::post())
+        .then({
+            let channels = channels.clone();
+            move |handle: u32| {
+                let channels = channels.clone();
+                async move {
+                    let request =
+                        CameraCommandRequest::File(CameraCommandFileRequest::Get { handle });
+                    camera_reply(&channels, request).await
+                }
+            }
+        });
+
+    let route_gimbal = warp::path!("api" / "gimbal" / String)
+        .and(warp::post())
+        .and(warp::body::json())
+        .then({
+            let channels = channels.clone();
+            move |command: String, fields: serde_json::Map<String, serde_json::Value>| {
+                let channels = channels.clone();
+                async move {
+                    match tagged_request("type", command, fields) {
+                        Ok(request) => {
+                            debug!("received gimbal command: {:?}", &request);
+                            command_reply(&channels.gimbal_cmd, request, "gimbal").await
+                        }
+                        Err(err) => err,
+                    }
+                }
+            }
+        });
+
+    let route_aux_camera = warp::path!("api" / "aux-camera" / String / String)
+        .and(warp::post())
+        .then({
+            let channels = channels.clone();
+            move |target: String, action: String| {
+                let channels = channels.clone();
+                async move { aux_camera_reply(&channels, &target, action).await }
+            }
+        });
+
+    let route_status = warp::path!("api" / "status").and(warp::get()).map({
+        let channels = channels.clone();
+        move || {
+            warp::reply::json(&serde_json::json!({
+                "ground_server": *channels.ground_server_status.borrow(),
+            }))
+        }
+    });
+
     let route_telem = warp::path!("api" / "telemetry" / "now")
         .and(warp::get())
         .and_then({
@@ -88,6 +305,22 @@ pub async fn serve(channels: Arc<Channels>, address: SocketAddr) -> anyhow::Resu
 
     let api = route_online
         .or(route_roi)
+        .or(route_roi_list)
+        .or(route_captures)
+        .or(route_pixhawk)
+        .or(route_mission)
+        .or(route_mission_upload)
+        .or(route_camera)
+        .or(route_camera_capture)
+        .or(route_camera_cc)
+        .or(route_camera_get)
+        .or(route_camera_set)
+        .or(route_camera_storage)
+        .or(route_camera_files)
+        .or(route_camera_download)
+        .or(route_gimbal)
+        .or(route_aux_camera)
+        .or(route_status)
         .or(route_telem)
         .or(route_telem_stream);
 
@@ -113,3 +346,122 @@ pub async fn serve(channels: Arc<Channels>, address: SocketAddr) -> anyhow::Resu
 
     Ok(())
 }
+
+/// Sends a request to the Pixhawk client and converts its response into a
+/// reply.
+async fn pixhawk_reply(channels: &Channels, request: PixhawkRequest) -> warp::reply::Response {
+    command_reply(&channels.pixhawk_cmd, request, "pixhawk").await
+}
+
+/// Sends a request to the main camera client and converts its response into a
+/// reply.
+async fn camera_reply(channels: &Channels, request: CameraCommandRequest) -> warp::reply::Response {
+    command_reply(&channels.camera_cmd, request, "camera").await
+}
+
+/// Sends a request to the aux camera's live stream or recorder, depending on
+/// `target`, and converts its response into a reply.
+#[cfg(feature = "gstreamer")]
+async fn aux_camera_reply(
+    channels: &Channels,
+    target: &str,
+    action: String,
+) -> warp::reply::Response {
+    let fields = serde_json::Map::new();
+
+    match target {
+        "stream" => match tagged_request("action", action, fields) {
+            Ok(request) => command_reply(&channels.stream_cmd, request, "aux camera stream").await,
+            Err(err) => err,
+        },
+        "save" => match tagged_request("action", action, fields) {
+            Ok(request) => command_reply(&channels.save_cmd, request, "aux camera save").await,
+            Err(err) => err,
+        },
+        _ => error_reply(
+            StatusCode::NOT_FOUND,
+            format!("unknown aux camera subsystem '{}'", target),
+        ),
+    }
+}
+
+#[cfg(not(feature = "gstreamer"))]
+async fn aux_camera_reply(
+    _channels: &Channels,
+    _target: &str,
+    _action: String,
+) -> warp::reply::Response {
+    error_reply(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "the plane system was built without aux camera support".to_owned(),
+    )
+}
+
+/// Sends a request to a client and converts its response into a reply. If the
+/// client is disabled in the config, its command channel is closed, and the
+/// reply is a 503; if the command fails, the reply is a 500.
+async fn command_reply<Req, Res: Serialize>(
+    sender: &flume::Sender<Command<Req, Res>>,
+    request: Req,
+    name: &str,
+) -> warp::reply::Response {
+    let (cmd, chan) = Command::new(request);
+
+    if let Err(err) = sender.send(cmd) {
+        return error_reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("{} client not available: {}", name, err),
+        );
+    }
+
+    match chan.await {
+        Ok(Ok(response)) => warp::reply::json(&response).into_response(),
+        Ok(Err(err)) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", err)),
+        Err(_) => error_reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("{} client dropped the command", name),
+        ),
+    }
+}
+
+/// Sends a command to the scheduler and converts its response into a reply.
+/// If the scheduler is not running, the reply is a 503.
+async fn scheduler_reply<Res: Serialize>(
+    channels: &Channels,
+    command: impl FnOnce(oneshot::Sender<Res>) -> SchedulerCommand,
+) -> warp::reply::Response {
+    let (tx, rx) = oneshot::channel();
+
+    if let Err(err) = channels.scheduler_cmd.send(command(tx)) {
+        return error_reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("scheduler not available: {}", err),
+        );
+    }
+
+    match rx.await {
+        Ok(response) => warp::reply::json(&response).into_response(),
+        Err(_) => error_reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "scheduler dropped the command".to_owned(),
+        ),
+    }
+}
+
+/// Parses a request whose variant is chosen by the path of the route, such as
+/// a camera property, from the `tag` property and the other `fields` of the
+/// request.
+fn tagged_request<T: DeserializeOwned>(
+    tag: &str,
+    value: String,
+    mut fields: serde_json::Map<String, serde_json::Value>,
+) -> Result<T, warp::reply::Response> {
+    fields.insert(tag.to_owned(), serde_json::Value::String(value));
+
+    serde_json::from_value(serde_json::Value::Object(fields))
+        .map_err(|err| error_reply(StatusCode::BAD_REQUEST, format!("invalid request: {}", err)))
+}
+
+fn error_reply(status: StatusCode, message: String) -> warp::reply::Response {
+    warp::reply::with_status(warp::reply::json(&message), status).into_response()
+}
@@ -40,25 +40,211 @@ impl Attitude {
     }
 }
 
+/// Attitude as a unit quaternion, which does not suffer from gimbal lock.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion {
+            w: 1.,
+            x: 0.,
+            y: 0.,
+            z: 0.,
+        }
+    }
+}
+
+impl Quaternion {
+    /// Converts roll, pitch and yaw (applied in that order, in degrees) into a
+    /// quaternion.
+    pub fn from_euler(attitude: &Attitude) -> Self {
+        let (sr, cr) = (attitude.roll.to_radians() / 2.).sin_cos();
+        let (sp, cp) = (attitude.pitch.to_radians() / 2.).sin_cos();
+        let (sy, cy) = (attitude.yaw.to_radians() / 2.).sin_cos();
+
+        Quaternion {
+            w: cr * cp * cy + sr * sp * sy,
+            x: sr * cp * cy - cr * sp * sy,
+            y: cr * sp * cy + sr * cp * sy,
+            z: cr * cp * sy - sr * sp * cy,
+        }
+    }
+
+    /// Converts this quaternion into roll, pitch and yaw in degrees.
+    pub fn to_euler(&self) -> Attitude {
+        let Quaternion { w, x, y, z } = *self;
+
+        let roll = (2. * (w * x + y * z)).atan2(1. - 2. * (x * x + y * y));
+        let pitch = (2. * (w * y - z * x)).clamp(-1., 1.).asin();
+        let yaw = (2. * (w * z + x * y)).atan2(1. - 2. * (y * y + z * z));
+
+        Attitude::new(roll.to_degrees(), pitch.to_degrees(), yaw.to_degrees())
+    }
+
+    fn dot(&self, other: &Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Spherical linear interpolation between `self` (at `t = 0`) and `other`
+    /// (at `t = 1`), taking the shortest path between them.
+    pub fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        let mut other = *other;
+        let mut dot = self.dot(&other);
+
+        // q and -q are the same rotation, so flip one of them if needed to
+        // avoid going the long way around
+        if dot < 0. {
+            other = Quaternion {
+                w: -other.w,
+                x: -other.x,
+                y: -other.y,
+                z: -other.z,
+            };
+            dot = -dot;
+        }
+
+        // when the quaternions are very close, sin(theta) is close to zero,
+        // so fall back to linear interpolation
+        let (a, b) = if dot > 0.9995 {
+            (1. - t, t)
+        } else {
+            let theta = dot.acos();
+            let sin_theta = theta.sin();
+            (
+                ((1. - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+
+        let q = Quaternion {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+        };
+
+        let norm = q.dot(&q).sqrt();
+
+        Quaternion {
+            w: q.w / norm,
+            x: q.x / norm,
+            y: q.y / norm,
+            z: q.z / norm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpsFix {
+    NoGps,
+    NoFix,
+    Fix2D,
+    Fix3D,
+    Dgps,
+    RtkFloat,
+    RtkFixed,
+    Static,
+    Ppp,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GpsStatus {
+    pub fix: GpsFix,
+
+    /// Number of satellites visible, if known
+    pub satellites: Option<u8>,
+
+    /// Horizontal dilution of precision, if known
+    pub hdop: Option<f32>,
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Speed {
+    /// Airspeed in meters per second
+    pub airspeed: f32,
+
+    /// Groundspeed in meters per second
+    pub groundspeed: f32,
+
+    /// Climb rate in meters per second
+    pub climb_rate: f32,
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Battery {
+    /// Battery voltage in volts, if known
+    pub voltage: Option<f32>,
+
+    /// Battery current in amps, if known
+    pub current: Option<f32>,
+
+    /// Remaining battery capacity in percent, if known
+    pub remaining: Option<u8>,
+}
+
 #[derive(Debug, Clone, Copy, Serialize)]
 pub struct Telemetry {
     pub plane_attitude: Attitude,
+    pub plane_attitude_quaternion: Option<Quaternion>,
+    /// When `plane_attitude` was last updated
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    pub attitude_timestamp: Option<chrono::DateTime<chrono::Local>>,
     pub gimbal_attitude: Attitude,
+    /// When `gimbal_attitude` was last updated
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    pub gimbal_attitude_timestamp: Option<chrono::DateTime<chrono::Local>>,
     pub position: Point3D,
     /// Velocity in meters per second (X, Y, Z) / (East, North, Up)
     pub velocity: (f32, f32, f32),
+    /// When `position` and `velocity` were last updated
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    pub position_timestamp: Option<chrono::DateTime<chrono::Local>>,
+    pub gps: Option<GpsStatus>,
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    pub gps_timestamp: Option<chrono::DateTime<chrono::Local>>,
+    pub speed: Option<Speed>,
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    pub speed_timestamp: Option<chrono::DateTime<chrono::Local>>,
+    pub battery: Option<Battery>,
+    #[serde(serialize_with = "crate::util::serialize_time_option")]
+    pub battery_timestamp: Option<chrono::DateTime<chrono::Local>>,
+    /// The index of the mission item that the plane is currently flying to
+    pub mission_item: Option<u16>,
+    /// When any part of this telemetry was last updated
     #[serde(serialize_with = "crate::util::serialize_time")]
     pub timestamp: chrono::DateTime<chrono::Local>,
+    /// Whether the link to the Pixhawk has been lost, in which case the rest
+    /// of this telemetry is out of date
+    pub stale: bool,
 }
 
 impl Default for Telemetry {
     fn default() -> Self {
         Telemetry {
             gimbal_attitude: Default::default(),
+            gimbal_attitude_timestamp: None,
             plane_attitude: Default::default(),
+            plane_attitude_quaternion: None,
+            attitude_timestamp: None,
             position: Default::default(),
             velocity: Default::default(),
+            position_timestamp: None,
+            gps: None,
+            gps_timestamp: None,
+            speed: None,
+            speed_timestamp: None,
+            battery: None,
+            battery_timestamp: None,
+            mission_item: None,
             timestamp: chrono::Local::now(),
+            stale: false,
         }
     }
 }
@@ -0,0 +1,410 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Local};
+
+use crate::state::{Attitude, Point3D, Quaternion, Telemetry};
+
+/// How far back in time telemetry is kept. This needs to cover the time
+/// between an image being captured and it being downloaded from the camera.
+const HISTORY_LENGTH: chrono::Duration = chrono::Duration::seconds(60);
+
+/// The closest that two samples in a series are allowed to be. The Pixhawk
+/// can send attitude at up to 1 kHz, which is far more than we need for
+/// interpolation, so samples that arrive faster than this replace the newest
+/// sample instead of being appended. This keeps each series to a few thousand
+/// entries, so pushing and looking up samples stays cheap while the lock is
+/// held.
+const MIN_SAMPLE_INTERVAL: chrono::Duration = chrono::Duration::milliseconds(10);
+
+/// A value that can be interpolated between two samples.
+trait Interpolate: Copy {
+    /// Returns the value at `t` between `self` (at `t = 0`) and `other` (at
+    /// `t = 1`).
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+/// The position and velocity from a single GPS message.
+#[derive(Debug, Clone, Copy)]
+struct PositionSample {
+    position: Point3D,
+    velocity: (f32, f32, f32),
+}
+
+impl Interpolate for PositionSample {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        PositionSample {
+            position: Point3D {
+                point: interpolate_point(self.position.point, other.position.point, t),
+                altitude_msl: lerp(self.position.altitude_msl, other.position.altitude_msl, t),
+                altitude_rel: lerp(self.position.altitude_rel, other.position.altitude_rel, t),
+            },
+            velocity: (
+                lerp(self.velocity.0, other.velocity.0, t),
+                lerp(self.velocity.1, other.velocity.1, t),
+                lerp(self.velocity.2, other.velocity.2, t),
+            ),
+        }
+    }
+}
+
+impl Interpolate for Attitude {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        Quaternion::from_euler(self)
+            .slerp(&Quaternion::from_euler(other), t)
+            .to_euler()
+    }
+}
+
+impl Interpolate for Quaternion {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.slerp(other, t)
+    }
+}
+
+/// Samples of a single kind of telemetry, each timestamped with the time that
+/// the message which carried it was received.
+#[derive(Debug)]
+struct Series<T> {
+    samples: VecDeque<(DateTime<Local>, T)>,
+}
+
+impl<T> Default for Series<T> {
+    fn default() -> Self {
+        Series {
+            samples: VecDeque::new(),
+        }
+    }
+}
+
+impl<T: Interpolate> Series<T> {
+    fn push(&mut self, timestamp: DateTime<Local>, value: T) {
+        let len = self.samples.len();
+
+        // samples must stay in order for the binary search in at()
+        if let Some((newest, _)) = self.samples.back() {
+            if timestamp < *newest {
+                warn!("dropping out of order telemetry sample");
+                return;
+            }
+        }
+
+        // if the newest sample is too close to the one before it, it is
+        // replaced, so that the newest sample is always up to date
+        if len >= 2 && self.samples[len - 1].0 - self.samples[len - 2].0 < MIN_SAMPLE_INTERVAL {
+            self.samples.pop_back();
+        }
+
+        let cutoff = timestamp - HISTORY_LENGTH;
+
+        while let Some((oldest, _)) = self.samples.front() {
+            if *oldest >= cutoff {
+                break;
+            }
+
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back((timestamp, value));
+    }
+
+    /// Estimates the value at `timestamp` by interpolating between the
+    /// samples on either side of it. If `timestamp` is newer than the newest
+    /// sample, the newest sample is returned. If it is older than the oldest
+    /// sample, `None` is returned.
+    fn at(&self, timestamp: DateTime<Local>) -> Option<T> {
+        let (newest_timestamp, newest) = self.samples.back()?;
+
+        if timestamp >= *newest_timestamp {
+            return Some(*newest);
+        }
+
+        // index of the first sample that is after the timestamp
+        let after = match self
+            .samples
+            .binary_search_by(|(sample_timestamp, _)| sample_timestamp.cmp(&timestamp))
+        {
+            Ok(idx) => return Some(self.samples[idx].1),
+            Err(0) => return None,
+            Err(idx) => idx,
+        };
+
+        let (before_timestamp, before) = &self.samples[after - 1];
+        let (after_timestamp, after) = &self.samples[after];
+
+        let span = (*after_timestamp - *before_timestamp).num_microseconds()? as f32;
+        let elapsed = (timestamp - *before_timestamp).num_microseconds()? as f32;
+
+        Some(before.interpolate(after, elapsed / span))
+    }
+}
+
+/// Recent telemetry that can be queried at arbitrary points in time.
+///
+/// Position, plane attitude and gimbal attitude come from different messages
+/// that arrive at different rates, so each of them is kept in its own series
+/// and only interpolated between its own samples.
+#[derive(Debug, Default)]
+pub struct TelemetryHistory {
+    position: Series<PositionSample>,
+    plane_attitude: Series<Attitude>,
+    plane_attitude_quaternion: Series<Quaternion>,
+    gimbal_attitude: Series<Attitude>,
+
+    /// The most recent telemetry, which is used for everything that changes
+    /// slowly enough that it doesn't need to be interpolated
+    latest: Option<Telemetry>,
+}
+
+impl TelemetryHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a position and velocity that were received at `timestamp`.
+    pub fn push_position(
+        &mut self,
+        timestamp: DateTime<Local>,
+        position: Point3D,
+        velocity: (f32, f32, f32),
+    ) {
+        self.position
+            .push(timestamp, PositionSample { position, velocity });
+    }
+
+    /// Records a plane attitude that was received at `timestamp`.
+    pub fn push_plane_attitude(&mut self, timestamp: DateTime<Local>, attitude: Attitude) {
+        self.plane_attitude.push(timestamp, attitude);
+    }
+
+    /// Records a plane attitude quaternion that was received at `timestamp`.
+    pub fn push_plane_attitude_quaternion(
+        &mut self,
+        timestamp: DateTime<Local>,
+        attitude: Quaternion,
+    ) {
+        self.plane_attitude_quaternion.push(timestamp, attitude);
+    }
+
+    /// Records a gimbal attitude that was received at `timestamp`.
+    pub fn push_gimbal_attitude(&mut self, timestamp: DateTime<Local>, attitude: Attitude) {
+        self.gimbal_attitude.push(timestamp, attitude);
+    }
+
+    /// Records the most recent telemetry.
+    pub fn set_latest(&mut self, telemetry: Telemetry) {
+        self.latest = Some(telemetry);
+    }
+
+    /// Estimates the telemetry at `timestamp`. Position is interpolated along
+    /// the great circle between the position samples on either side of it,
+    /// and attitudes are interpolated with slerp between their own samples.
+    /// Anything that is not covered by a series is taken from the most recent
+    /// telemetry.
+    ///
+    /// Returns `None` if `timestamp` is older than the oldest position
+    /// sample.
+    pub fn telemetry_at(&self, timestamp: DateTime<Local>) -> Option<Telemetry> {
+        let mut telemetry = self.latest?;
+        let position = self.position.at(timestamp)?;
+
+        telemetry.position = position.position;
+        telemetry.velocity = position.velocity;
+        telemetry.position_timestamp = Some(timestamp);
+
+        if let Some(attitude) = self.plane_attitude.at(timestamp) {
+            telemetry.plane_attitude = attitude;
+            telemetry.attitude_timestamp = Some(timestamp);
+        }
+
+        if let Some(attitude) = self.plane_attitude_quaternion.at(timestamp) {
+            telemetry.plane_attitude_quaternion = Some(attitude);
+        }
+
+        if let Some(attitude) = self.gimbal_attitude.at(timestamp) {
+            telemetry.gimbal_attitude = attitude;
+            telemetry.gimbal_attitude_timestamp = Some(timestamp);
+        }
+
+        telemetry.timestamp = timestamp;
+
+        Some(telemetry)
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Interpolates between two points along the great circle that connects them.
+fn interpolate_point(a: geo::Point<f32>, b: geo::Point<f32>, t: f32) -> geo::Point<f32> {
+    fn to_vector(p: geo::Point<f32>) -> [f64; 3] {
+        let (lat, lon) = ((p.y() as f64).to_radians(), (p.x() as f64).to_radians());
+        [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+    }
+
+    let (va, vb) = (to_vector(a), to_vector(b));
+    let t = t as f64;
+
+    let dot = (va[0] * vb[0] + va[1] * vb[1] + va[2] * vb[2]).clamp(-1., 1.);
+    let angle = dot.acos();
+
+    // the samples are close together, so this is the common case
+    if angle < 1e-9 {
+        return geo::Point::new(lerp(a.x(), b.x(), t as f32), lerp(a.y(), b.y(), t as f32));
+    }
+
+    let sin_angle = angle.sin();
+    let wa = ((1. - t) * angle).sin() / sin_angle;
+    let wb = (t * angle).sin() / sin_angle;
+
+    let v = [
+        wa * va[0] + wb * vb[0],
+        wa * va[1] + wb * vb[1],
+        wa * va[2] + wb * vb[2],
+    ];
+
+    let lat = v[2].atan2((v[0] * v[0] + v[1] * v[1]).sqrt());
+    let lon = v[1].atan2(v[0]);
+
+    geo::Point::new(lon.to_degrees() as f32, lat.to_degrees() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t0() -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn ms(n: i64) -> chrono::Duration {
+        chrono::Duration::milliseconds(n)
+    }
+
+    fn position(x: f32, y: f32, altitude: f32) -> Point3D {
+        Point3D {
+            point: geo::Point::new(x, y),
+            altitude_msl: altitude,
+            altitude_rel: altitude,
+        }
+    }
+
+    fn yaw(yaw: f32) -> Attitude {
+        Attitude::new(0., 0., yaw)
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn interpolates_along_great_circle() {
+        let midpoint = interpolate_point(geo::Point::new(0., 0.), geo::Point::new(90., 0.), 0.5);
+        assert_close(midpoint.x(), 45., 1e-4);
+        assert_close(midpoint.y(), 0., 1e-4);
+
+        // the great circle between two points on the same parallel bulges
+        // towards the pole: tan(lat) = tan(45°) / cos(45°)
+        let midpoint =
+            interpolate_point(geo::Point::new(-45., 45.), geo::Point::new(45., 45.), 0.5);
+        assert_close(midpoint.x(), 0., 1e-4);
+        assert_close(midpoint.y(), 54.7356, 1e-3);
+    }
+
+    #[test]
+    fn telemetry_is_interpolated_between_samples() {
+        let t0 = t0();
+        let mut history = TelemetryHistory::new();
+
+        history.set_latest(Telemetry::default());
+        history.push_position(t0, position(-76.48, 42.44, 100.), (10., 0., 0.));
+        history.push_position(t0 + ms(1000), position(-76.46, 42.44, 120.), (20., 0., 0.));
+
+        let telemetry = history.telemetry_at(t0 + ms(250)).unwrap();
+
+        assert_close(telemetry.position.point.x(), -76.475, 1e-4);
+        assert_close(telemetry.position.point.y(), 42.44, 1e-4);
+        assert_close(telemetry.position.altitude_rel, 105., 1e-3);
+        assert_close(telemetry.velocity.0, 12.5, 1e-3);
+        assert_eq!(telemetry.timestamp, t0 + ms(250));
+    }
+
+    #[test]
+    fn yaw_is_interpolated_across_180_degrees() {
+        let t0 = t0();
+        let mut series = Series::default();
+
+        series.push(t0, yaw(170.));
+        series.push(t0 + ms(1000), yaw(-170.));
+
+        // the short way around is through 180°, not through 0°
+        assert_close(series.at(t0 + ms(250)).unwrap().yaw, 175., 1e-2);
+        assert_close(series.at(t0 + ms(500)).unwrap().yaw.abs(), 180., 1e-2);
+        assert_close(series.at(t0 + ms(750)).unwrap().yaw, -175., 1e-2);
+    }
+
+    #[test]
+    fn nothing_is_known_before_the_oldest_sample() {
+        let t0 = t0();
+        let mut series = Series::default();
+
+        assert!(series.at(t0).is_none());
+
+        series.push(t0, yaw(10.));
+        series.push(t0 + ms(1000), yaw(20.));
+
+        assert!(series.at(t0 - ms(1)).is_none());
+        assert_close(series.at(t0).unwrap().yaw, 10., 1e-3);
+
+        // newer than the newest sample, so the newest sample is used
+        assert_close(series.at(t0 + ms(5000)).unwrap().yaw, 20., 1e-3);
+
+        let mut history = TelemetryHistory::new();
+        history.set_latest(Telemetry::default());
+        history.push_position(t0, position(-76.48, 42.44, 100.), (0., 0., 0.));
+
+        assert!(history.telemetry_at(t0 - ms(1)).is_none());
+    }
+
+    #[test]
+    fn samples_closer_than_min_interval_replace_the_newest() {
+        let t0 = t0();
+        let mut series = Series::default();
+
+        series.push(t0, yaw(0.));
+        series.push(t0 + ms(100), yaw(10.));
+        series.push(t0 + ms(105), yaw(11.));
+        series.push(t0 + ms(108), yaw(12.));
+
+        let timestamps = series
+            .samples
+            .iter()
+            .map(|(timestamp, _)| *timestamp - t0)
+            .collect::<Vec<_>>();
+
+        assert_eq!(timestamps, [ms(0), ms(100), ms(108)]);
+        assert_close(series.at(t0 + ms(108)).unwrap().yaw, 12., 1e-3);
+    }
+
+    #[test]
+    fn out_of_order_and_expired_samples_are_dropped() {
+        let t0 = t0();
+        let mut series = Series::default();
+
+        series.push(t0, yaw(0.));
+        series.push(t0 + ms(1000), yaw(10.));
+        series.push(t0 + ms(500), yaw(5.));
+
+        assert_eq!(series.samples.len(), 2);
+
+        series.push(t0 + HISTORY_LENGTH + ms(500), yaw(20.));
+
+        assert_eq!(series.samples.len(), 2);
+        assert!(series.at(t0 + ms(500)).is_none());
+    }
+}
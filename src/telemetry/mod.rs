@@ -1,24 +1,42 @@
-use crate::{pixhawk::state::PixhawkEvent, state::Telemetry, util::ReceiverExt, Channels};
+use crate::{
+    cli::config::{TelemetryConfig, TelemetryPublishPolicy},
+    gimbal::GimbalEvent,
+    pixhawk::state::PixhawkEvent,
+    state::Telemetry,
+    util::ReceiverExt,
+    Channels,
+};
+
+mod history;
+
+pub use history::TelemetryHistory;
 
 use std::sync::{Arc, Mutex};
 
 use anyhow::Context;
 use std::time::Duration;
-use tokio::time::interval;
-use tokio::{spawn, sync::watch};
+use tokio::time::{interval, sleep};
+use tokio::{
+    spawn,
+    sync::{watch, Notify},
+};
 
 // Noteworthy that this isn't a RwLock because we have at most one reader at any given moment
 type TelemetryState = Arc<Mutex<Telemetry>>;
 
 struct TelemetryCollector {
     state: TelemetryState,
+    /// Notified whenever the collector changes the telemetry
+    changed: Arc<Notify>,
     channels: Arc<Channels>,
 }
 
 struct TelemetryPublisher {
     state: TelemetryState,
+    changed: Arc<Notify>,
     sender: watch::Sender<Option<Telemetry>>,
     channels: Arc<Channels>,
+    policy: TelemetryPublishPolicy,
 }
 
 pub struct TelemetryStream {
@@ -27,41 +45,116 @@ pub struct TelemetryStream {
 }
 
 impl TelemetryCollector {
-    fn new(telemetry_state: TelemetryState, channels: Arc<Channels>) -> Self {
+    fn new(telemetry_state: TelemetryState, changed: Arc<Notify>, channels: Arc<Channels>) -> Self {
         Self {
             state: telemetry_state,
+            changed,
             channels,
         }
     }
 
+    /// Merges an event from the Pixhawk into the telemetry. Returns whether
+    /// the telemetry changed.
+    fn handle_pixhawk(&self, message: PixhawkEvent) -> bool {
+        let now = chrono::Local::now();
+        let mut state = self.state.lock().unwrap();
+
+        // samples in the history are timestamped with the arrival of the
+        // message that carried them, so that position and attitude are only
+        // interpolated between their own samples
+        let mut history = self.channels.telemetry_history.lock().unwrap();
+
+        match message {
+            PixhawkEvent::Gps { position, velocity } => {
+                state.position = position;
+                state.velocity = velocity;
+                state.position_timestamp = Some(now);
+                history.push_position(now, position, velocity);
+            }
+            PixhawkEvent::Orientation { attitude } => {
+                state.plane_attitude = attitude;
+                state.attitude_timestamp = Some(now);
+                history.push_plane_attitude(now, attitude);
+            }
+            PixhawkEvent::OrientationQuaternion { attitude } => {
+                state.plane_attitude_quaternion = Some(attitude);
+                state.attitude_timestamp = Some(now);
+                history.push_plane_attitude_quaternion(now, attitude);
+            }
+            PixhawkEvent::GpsStatus(gps) => {
+                state.gps = Some(gps);
+                state.gps_timestamp = Some(now);
+            }
+            PixhawkEvent::Speed(speed) => {
+                state.speed = Some(speed);
+                state.speed_timestamp = Some(now);
+            }
+            PixhawkEvent::Battery(battery) => {
+                state.battery = Some(battery);
+                state.battery_timestamp = Some(now);
+            }
+            PixhawkEvent::MissionCurrent { seq } => {
+                state.mission_item = Some(seq);
+            }
+            PixhawkEvent::LinkLost => {
+                state.stale = true;
+            }
+            PixhawkEvent::Heartbeat { .. } | PixhawkEvent::LinkRestored => {
+                state.stale = false;
+            }
+            _ => return false,
+        }
+
+        state.timestamp = now;
+        history.set_latest(*state);
+
+        true
+    }
+
+    /// Merges an event from the gimbal into the telemetry. Returns whether
+    /// the telemetry changed.
+    fn handle_gimbal(&self, message: GimbalEvent) -> bool {
+        let now = chrono::Local::now();
+        let mut state = self.state.lock().unwrap();
+        let mut history = self.channels.telemetry_history.lock().unwrap();
+
+        match message {
+            GimbalEvent::Attitude { attitude } => {
+                state.gimbal_attitude = attitude;
+                state.gimbal_attitude_timestamp = Some(now);
+                history.push_gimbal_attitude(now, attitude);
+            }
+        }
+
+        state.timestamp = now;
+        history.set_latest(*state);
+
+        true
+    }
+
     async fn run(&self) -> anyhow::Result<()> {
         let mut interrupt_recv = self.channels.interrupt.subscribe();
         let interrupt_fut = interrupt_recv.recv();
 
-        // pixhawk_recv can block indefinitely if the pixhawk is disabled; there
-        // is no cleanup for telemetry stream so we can just do a select
+        // pixhawk_recv and gimbal_recv can block indefinitely if the pixhawk or
+        // gimbal is disabled; there is no cleanup for telemetry stream so we
+        // can just do a select
         let loop_fut = async {
             let mut pixhawk_recv = self.channels.pixhawk_event.subscribe();
+            let mut gimbal_recv = self.channels.gimbal_event.subscribe();
 
             loop {
-                let message = pixhawk_recv
-                    .recv_skip()
-                    .await
-                    .context("pixhawk stream closed")?;
-
-                match message {
-                    PixhawkEvent::Gps { position, velocity } => {
-                        let mut state = self.state.lock().unwrap();
-                        state.position = position;
-                        state.velocity = velocity;
-                        state.timestamp = chrono::Local::now();
+                let changed = tokio::select! {
+                    message = pixhawk_recv.recv_skip() => {
+                        self.handle_pixhawk(message.context("pixhawk stream closed")?)
                     }
-                    PixhawkEvent::Orientation { attitude } => {
-                        let mut state = self.state.lock().unwrap();
-                        state.plane_attitude = attitude;
-                        state.timestamp = chrono::Local::now();
+                    message = gimbal_recv.recv_skip() => {
+                        self.handle_gimbal(message.context("gimbal stream closed")?)
                     }
-                    _ => {}
+                };
+
+                if changed {
+                    self.changed.notify_one();
                 }
             }
 
@@ -82,45 +175,92 @@ impl TelemetryCollector {
 impl TelemetryPublisher {
     fn new(
         state: TelemetryState,
+        changed: Arc<Notify>,
         sender: watch::Sender<Option<Telemetry>>,
         channels: Arc<Channels>,
+        policy: TelemetryPublishPolicy,
     ) -> Self {
         Self {
             state,
+            changed,
             sender,
             channels,
+            policy,
         }
     }
 
+    /// Sends the current telemetry to subscribers. Returns `false` if there
+    /// are no subscribers left.
+    fn publish(&self) -> bool {
+        let telemetry = *self.state.lock().unwrap();
+        self.sender.send(Some(telemetry)).is_ok()
+    }
+
     async fn run(&self) -> anyhow::Result<()> {
         let mut interrupt_recv = self.channels.interrupt.subscribe();
+        let interrupt_fut = interrupt_recv.recv();
 
-        let mut interval = interval(Duration::from_millis(500));
+        let loop_fut = async {
+            match self.policy {
+                TelemetryPublishPolicy::OnChange => loop {
+                    self.changed.notified().await;
+
+                    if !self.publish() {
+                        break;
+                    }
+                },
+                TelemetryPublishPolicy::FixedRate { interval_ms } => {
+                    let mut interval = interval(Duration::from_millis(interval_ms));
 
-        loop {
-            if let Ok(telemetry) = self.state.lock() {
-                if let Err(_) = self.sender.send(Some(telemetry.clone())) {
-                    break;
+                    loop {
+                        interval.tick().await;
+
+                        if !self.publish() {
+                            break;
+                        }
+                    }
                 }
-            }
+                TelemetryPublishPolicy::RateLimited { min_interval_ms } => loop {
+                    // notify_one stores a permit if nothing is waiting, so a
+                    // change that happens while we are sleeping is published
+                    // as soon as we wake up
+                    self.changed.notified().await;
 
-            if interrupt_recv.try_recv().is_ok() {
-                break;
+                    if !self.publish() {
+                        break;
+                    }
+
+                    sleep(Duration::from_millis(min_interval_ms)).await;
+                },
             }
+        };
 
-            interval.tick().await;
-        }
+        futures::pin_mut!(loop_fut);
+        futures::pin_mut!(interrupt_fut);
+        futures::future::select(interrupt_fut, loop_fut).await;
 
         Ok(())
     }
 }
 
 impl TelemetryStream {
-    pub fn new(channels: Arc<Channels>, sender: watch::Sender<Option<Telemetry>>) -> Self {
+    pub fn new(
+        channels: Arc<Channels>,
+        sender: watch::Sender<Option<Telemetry>>,
+        config: TelemetryConfig,
+    ) -> Self {
         let telemetry_state = Arc::new(Mutex::new(Telemetry::default()));
+        let changed = Arc::new(Notify::new());
 
-        let collector = TelemetryCollector::new(telemetry_state.clone(), channels.clone());
-        let publisher = TelemetryPublisher::new(telemetry_state.clone(), sender, channels.clone());
+        let collector =
+            TelemetryCollector::new(telemetry_state.clone(), changed.clone(), channels.clone());
+        let publisher = TelemetryPublisher::new(
+            telemetry_state,
+            changed,
+            sender,
+            channels.clone(),
+            config.publish,
+        );
 
         Self {
             collector,
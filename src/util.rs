@@ -19,6 +19,19 @@ where
     serializer.collect_str(&this.format(ISO_8601_FORMAT).to_string())
 }
 
+pub fn serialize_time_option<S>(
+    this: &Option<chrono::DateTime<chrono::Local>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::ser::Serializer,
+{
+    match this {
+        Some(this) => serialize_time(this, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
 pub fn serialize_point<S>(this: &geo::Point<f32>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::ser::Serializer,